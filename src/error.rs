@@ -0,0 +1,94 @@
+use std::fmt;
+
+//////////////////////////////////////////////////////////
+// heron のエラー
+//-----------------------------------------
+// CLI は exit_code を終了コードにする
+//
+// | variant             | exit code | 原因                               |
+// |---------------------|-----------|------------------------------------|
+// | Input               | 2         | 不正なオプション，開けないファイル |
+// | Parse               | 3         | 入力の日付や行の形式               |
+// | InsufficientHistory | 4         | 予測に使える予定がない             |
+// | SingularRegression  | 5         | 回帰の解が求まらない               |
+// | NoCandidates        | 6         | 全ての候補日が制約に反する         |
+// | Auth                | 7         | token.json, credentials.json, 401  |
+// | Api                 | 8         | Calendar API の通信と応答          |
+// | Internal            | 9         | 予測中の panic (batch の 1 行)     |
+//////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeronError {
+    Input(String),
+    Parse {
+        path: Option<String>,
+        line: usize,
+        message: String,
+    },
+    InsufficientHistory {
+        found: usize,
+        required: usize,
+    },
+    SingularRegression(String),
+    NoCandidates(String),
+    Auth(String),
+    Api(String),
+    Internal(String),
+}
+
+impl HeronError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HeronError::Input(_) => 2,
+            HeronError::Parse { .. } => 3,
+            HeronError::InsufficientHistory { .. } => 4,
+            HeronError::SingularRegression(_) => 5,
+            HeronError::NoCandidates(_) => 6,
+            HeronError::Auth(_) => 7,
+            HeronError::Api(_) => 8,
+            HeronError::Internal(_) => 9,
+        }
+    }
+
+    // Parse に読んでいたファイル名を付ける
+    pub fn in_file(self, path: &str) -> HeronError {
+        match self {
+            HeronError::Parse { line, message, .. } => HeronError::Parse {
+                path: Some(path.to_string()),
+                line,
+                message,
+            },
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for HeronError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeronError::Input(m) => write!(f, "{}", m),
+            HeronError::Parse {
+                path: Some(p),
+                line,
+                message,
+            } => write!(f, "{}: line {}: {}", p, line, message),
+            HeronError::Parse {
+                path: None,
+                line,
+                message,
+            } => write!(f, "line {}: {}", line, message),
+            HeronError::InsufficientHistory { found: 0, .. } => write!(f, "No occurrences"),
+            HeronError::InsufficientHistory { found, required } => write!(
+                f,
+                "Not enough occurrences: {} (at least {})",
+                found, required
+            ),
+            HeronError::SingularRegression(m) => write!(f, "Singular regression: {}", m),
+            HeronError::NoCandidates(m) => write!(f, "{}", m),
+            HeronError::Auth(m) => write!(f, "Authorization failed: {}", m),
+            HeronError::Api(m) => write!(f, "Calendar API error: {}", m),
+            HeronError::Internal(m) => write!(f, "Internal error: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for HeronError {}
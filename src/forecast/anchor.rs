@@ -0,0 +1,183 @@
+use chrono::{Date, Duration, Utc};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::forecaster::get_params;
+use super::holiday::HolidayProvider;
+use super::method::{Forecaster, Options};
+use super::result::{Candidate, Forecast};
+use crate::error::HeronError;
+
+// 対応づける予定の差 (日) の上限
+pub const MAX_OFFSET: i64 = 90;
+// 差の分布を使うのに必要な組の数
+const MIN_PAIRS: usize = 2;
+// 差の分布を平滑化するカーネルの幅 (日)
+const BANDWIDTH: f64 = 1.0;
+// 差の分布と単独のモデルを混ぜる割合
+const ANCHOR_WEIGHT: f64 = 0.8;
+
+///////////////////////////////////////////
+// Learn offsets from the anchor
+//-----------------------------------------
+// anchors : 基準となるリカーレンスの予定 (日付順)
+// events  : 予測するリカーレンスの予定
+// return  : 各予定から最も近い基準の予定までの差 (日)
+//           MAX_OFFSET より離れた予定は使わない
+///////////////////////////////////////////
+// Example
+//-----------------------------------------
+// anchors : vec!['2020/2/1', '2021/2/1']
+// events  : vec!['2020/1/22', '2021/1/22', '2021/7/1']
+// return  : vec![-10, -10]
+///////////////////////////////////////////
+pub fn learn_offsets(anchors: &Vec<Date<Utc>>, events: &Vec<Date<Utc>>) -> Vec<i64> {
+    events
+        .iter()
+        .filter_map(|e| {
+            anchors
+                .iter()
+                .map(|a| (*e - *a).num_days())
+                .min_by_key(|d| d.abs())
+        })
+        .filter(|d| d.abs() <= MAX_OFFSET)
+        .collect()
+}
+
+fn median(offsets: &Vec<i64>) -> i64 {
+    let mut sorted = offsets.clone();
+    sorted.sort();
+    sorted[sorted.len() / 2]
+}
+
+// 差 offset のガウスカーネル密度
+fn density(offsets: &Vec<i64>, offset: i64) -> f64 {
+    offsets
+        .iter()
+        .map(|o| {
+            let z = (offset - o) as f64 / BANDWIDTH;
+            (-0.5 * z * z).exp()
+        })
+        .sum::<f64>()
+        / offsets.len() as f64
+}
+
+// 合計が 1 になるように正規化する (負のスコアは 0 とみなす)
+fn normalize(scores: &mut BTreeMap<Date<Utc>, f64>) {
+    let total: f64 = scores.values().map(|s| s.max(0.0)).sum();
+    for s in scores.values_mut() {
+        *s = if total > 0.0 { s.max(0.0) / total } else { 0.0 };
+    }
+}
+
+///////////////////////////////////////////
+// Anchor-relative forecasting
+//-----------------------------------------
+// 「入試の 10 日前の委員会」のように別のリカーレンス
+// (anchor) に対して決まる予定を予測する．
+// anchor との差の分布から anchor の (実績または予測の)
+// 日付に対する候補日を作り，単独のモデルのスコアと
+// ANCHOR_WEIGHT : 1 - ANCHOR_WEIGHT で混ぜて順位をつける．
+// 対応する anchor の予定が足りないときは単独のモデルの
+// 結果をそのまま返す
+///////////////////////////////////////////
+pub struct AnchorForecaster {
+    base: Box<dyn Forecaster>,
+    anchors: Vec<Date<Utc>>,
+    holidays: Arc<dyn HolidayProvider>,
+    offsets: Vec<i64>,
+    range_candidate: Vec<i64>,
+}
+
+impl AnchorForecaster {
+    pub fn new(
+        base: Box<dyn Forecaster>,
+        mut anchors: Vec<Date<Utc>>,
+        options: &Options,
+    ) -> AnchorForecaster {
+        anchors.sort();
+        anchors.dedup();
+        AnchorForecaster {
+            base,
+            anchors,
+            holidays: options.holidays.clone(),
+            offsets: vec![],
+            range_candidate: vec![0],
+        }
+    }
+}
+
+impl Forecaster for AnchorForecaster {
+    fn fit(
+        &mut self,
+        events: &Vec<Date<Utc>>,
+        range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+    ) -> Result<(), HeronError> {
+        self.base.fit(events, range_recurrence, range_candidate)?;
+        self.offsets = learn_offsets(&self.anchors, events);
+        self.range_candidate = range_candidate.clone();
+        Ok(())
+    }
+
+    fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast {
+        let base = self.base.rank(events);
+        if self.offsets.len() < MIN_PAIRS {
+            return base;
+        }
+        let latest = match events.last() {
+            Some(d) => *d,
+            None => return base,
+        };
+
+        // まだ対応する予定のない最初の anchor
+        let center = median(&self.offsets);
+        let anchor = match self
+            .anchors
+            .iter()
+            .find(|a| **a + Duration::days(center) > latest)
+        {
+            Some(a) => *a,
+            None => return base,
+        };
+
+        let width_min = *self.range_candidate.iter().min().unwrap_or(&0);
+        let width_max = *self.range_candidate.iter().max().unwrap_or(&0);
+        let from = self.offsets.iter().min().unwrap() + width_min;
+        let to = self.offsets.iter().max().unwrap() + width_max;
+
+        let mut by_anchor: BTreeMap<Date<Utc>, f64> = (from..=to)
+            .map(|o| (anchor + Duration::days(o), density(&self.offsets, o)))
+            .collect();
+        normalize(&mut by_anchor);
+        let mut by_base: BTreeMap<Date<Utc>, f64> =
+            base.candidates.iter().map(|c| (c.date, c.score)).collect();
+        normalize(&mut by_base);
+
+        let mut dates: Vec<Date<Utc>> = by_anchor.keys().chain(by_base.keys()).cloned().collect();
+        dates.sort();
+        dates.dedup();
+
+        let candidates = dates
+            .into_iter()
+            .map(|date| {
+                let score = ANCHOR_WEIGHT * by_anchor.get(&date).unwrap_or(&0.0)
+                    + (1.0 - ANCHOR_WEIGHT) * by_base.get(&date).unwrap_or(&0.0);
+                let mut candidate = match base.candidates.iter().find(|c| c.date == date) {
+                    Some(c) => c.clone(),
+                    None => Candidate::new(date, 0.0, get_params(&date, self.holidays.as_ref())),
+                };
+                candidate.score = score;
+                candidate.probability = None;
+                candidate.anchor = Some(anchor);
+                candidate
+            })
+            .collect();
+
+        let mut forecast = Forecast::new(candidates);
+        forecast.periods = base.periods.clone();
+        forecast.intercept = base.intercept;
+        forecast.fallback = base.fallback;
+        forecast
+    }
+}
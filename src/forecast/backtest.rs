@@ -0,0 +1,121 @@
+use chrono::{Date, Utc};
+use serde::Serialize;
+
+use super::fiscal_year::FiscalYear;
+use super::method::Forecaster;
+use crate::error::HeronError;
+
+// 学習に最低限必要な予定数
+pub const MIN_HISTORY: usize = 3;
+// evaluate の --holdout と --tolerance を省略したとき
+pub const DEFAULT_HOLDOUT: usize = 3;
+pub const DEFAULT_TOLERANCE: i64 = 3;
+
+///////////////////////////////////////////
+// Rolling-origin backtest
+//-----------------------------------------
+// 末尾の holdout 件を 1 件ずつ隠し，それより前の
+// 履歴だけで学習して隠した予定を予測する
+//
+// holdout         : 隠す予定の数
+// tolerance       : ±tolerance 日以内を的中とみなす
+// range_candidate : 候補日の範囲
+// fiscal_year     : サンプリング範囲の開始 (年度初め)
+// sampling_years  : 直近何年度分で学習するか (None なら全履歴)
+///////////////////////////////////////////
+pub struct Backtest {
+    pub holdout: usize,
+    pub tolerance: i64,
+    pub range_candidate: Vec<i64>,
+    pub fiscal_year: FiscalYear,
+    pub sampling_years: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub actual: Date<Utc>,
+    pub forecast: Date<Utc>,
+    // forecast - actual (日)
+    pub error: i64,
+}
+
+impl Backtest {
+    pub fn run(
+        &self,
+        forecaster: &mut dyn Forecaster,
+        events: &Vec<Date<Utc>>,
+    ) -> Result<Vec<Trial>, HeronError> {
+        let n = events.len();
+        let from = n.saturating_sub(self.holdout).max(MIN_HISTORY);
+        let mut trials = vec![];
+
+        for i in from..n {
+            let history = events[..i].to_vec();
+            let range = self
+                .fiscal_year
+                .sampling_range(&history, self.sampling_years);
+            forecaster.fit(&history, &range, &self.range_candidate)?;
+
+            let forecast = match forecaster.predict(&history) {
+                Some(d) => d,
+                None => {
+                    return Err(HeronError::NoCandidates(format!(
+                        "No candidates after {}",
+                        history[i - 1].format("%Y-%m-%d")
+                    )))
+                }
+            };
+            trials.push(Trial {
+                actual: events[i],
+                forecast,
+                error: (forecast - events[i]).num_days(),
+            });
+        }
+        Ok(trials)
+    }
+}
+
+///////////////////////////////////////////
+// Accuracy metrics
+//-----------------------------------------
+// mae         : 平均絶対誤差 (日)
+// exact_rate  : 完全一致の割合
+// within_rate : ±tolerance 日以内の割合
+///////////////////////////////////////////
+#[derive(Debug, Clone, Serialize)]
+pub struct Evaluation {
+    pub recurrence: String,
+    pub method: String,
+    pub trials: usize,
+    pub mae: f64,
+    pub exact_rate: f64,
+    pub within_rate: f64,
+    pub tolerance: i64,
+}
+
+impl Evaluation {
+    pub fn new(recurrence: &str, method: &str, trials: &Vec<Trial>, tolerance: i64) -> Evaluation {
+        let n = trials.len() as f64;
+        let rate = |hit: &dyn Fn(&Trial) -> bool| {
+            if trials.is_empty() {
+                return 0.0;
+            }
+            trials.iter().filter(|t| hit(t)).count() as f64 / n
+        };
+        let mae = if trials.is_empty() {
+            0.0
+        } else {
+            trials.iter().map(|t| t.error.abs() as f64).sum::<f64>() / n
+        };
+
+        Evaluation {
+            recurrence: recurrence.to_string(),
+            method: method.to_string(),
+            trials: trials.len(),
+            mae,
+            exact_rate: rate(&|t| t.error == 0),
+            within_rate: rate(&|t| t.error.abs() <= tolerance),
+            tolerance,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use chrono::{Date, NaiveDate, Utc};
+use serde::Serialize;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use super::result::Forecast;
+use crate::error::HeronError;
+
+// --jobs を省略したときのスレッド数
+pub const DEFAULT_JOBS: usize = 4;
+
+///////////////////////////////////////////
+// 1 つのリカーレンスの予定履歴
+///////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    pub events: Vec<Date<Utc>>,
+}
+
+///////////////////////////////////////////
+// 複数リカーレンスの入力
+//-----------------------------------------
+// 1 行に "リカーレンス名,YYYY-MM-DD" を書く
+// 空行と # で始まる行は読み飛ばす
+// リカーレンスは初めて現れた順に並べ，予定は日付順にして重複を除く
+//
+// | 入力                 | Series                          |
+// |----------------------|---------------------------------|
+// | seminar,2020-04-08   | seminar: 2020-04-08, 2021-04-07 |
+// | meeting,2020-04-15   | meeting: 2020-04-15             |
+// | seminar,2021-04-07   |                                 |
+///////////////////////////////////////////
+pub fn parse_series(lines: &Vec<String>) -> Result<Vec<Series>, HeronError> {
+    let mut series: Vec<Series> = vec![];
+
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut cols = line.splitn(2, ',');
+        let name = cols.next().unwrap_or("").trim();
+        let date = cols.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err(HeronError::Parse {
+                path: None,
+                line: i + 1,
+                message: "missing recurrence name".to_string(),
+            });
+        }
+        let date = match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(d) => Date::from_utc(d, Utc),
+            Err(_) => {
+                return Err(HeronError::Parse {
+                    path: None,
+                    line: i + 1,
+                    message: format!("invalid date: {}", date),
+                })
+            }
+        };
+
+        match series.iter_mut().find(|s| s.name == name) {
+            Some(s) => s.events.push(date),
+            None => series.push(Series {
+                name: name.to_string(),
+                events: vec![date],
+            }),
+        }
+    }
+
+    for s in series.iter_mut() {
+        s.events.sort();
+        s.events.dedup();
+    }
+    Ok(series)
+}
+
+///////////////////////////////////////////
+// 並列実行
+//-----------------------------------------
+// jobs 本のスレッドで series を順に取り出して f を適用する
+// 結果は series と同じ順に返す
+// f が panic したリカーレンスは Internal エラーにして残りを続ける
+///////////////////////////////////////////
+pub fn run<T, F>(series: Vec<Series>, jobs: usize, f: F) -> Vec<(Series, Result<T, HeronError>)>
+where
+    T: Send + 'static,
+    F: Fn(&Series) -> Result<T, HeronError> + Send + Sync + 'static,
+{
+    let n = series.len();
+    let series = Arc::new(series);
+    let f = Arc::new(f);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+
+    let mut handles = vec![];
+    for _ in 0..jobs.max(1).min(n) {
+        let series = series.clone();
+        let f = f.clone();
+        let next = next.clone();
+        let tx = tx.clone();
+        handles.push(thread::spawn(move || loop {
+            let i = next.fetch_add(1, Ordering::SeqCst);
+            if i >= series.len() {
+                break;
+            }
+            let r = panic::catch_unwind(AssertUnwindSafe(|| f(&series[i])))
+                .unwrap_or_else(|p| Err(HeronError::Internal(panic_message(p))));
+            if tx.send((i, r)).is_err() {
+                break;
+            }
+        }));
+    }
+    drop(tx);
+
+    let mut results: Vec<Option<Result<T, HeronError>>> = (0..n).map(|_| None).collect();
+    for (i, r) in rx {
+        results[i] = Some(r);
+    }
+    for h in handles {
+        let _ = h.join();
+    }
+
+    series
+        .iter()
+        .cloned()
+        .zip(
+            results
+                .into_iter()
+                .map(|r| r.unwrap_or_else(|| Err(HeronError::Internal("no result".to_string())))),
+        )
+        .collect()
+}
+
+// panic! に渡されたメッセージを取り出す
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(m) = payload.downcast_ref::<&str>() {
+        m.to_string()
+    } else if let Some(m) = payload.downcast_ref::<String>() {
+        m.clone()
+    } else {
+        "panicked".to_string()
+    }
+}
+
+///////////////////////////////////////////
+// まとめて出力するための結果
+///////////////////////////////////////////
+#[derive(Debug, Clone, Serialize)]
+pub struct Forecasted {
+    pub date: String,
+    pub score: f64,
+    pub probability: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub recurrence: String,
+    pub occurrences: usize,
+    pub forecasts: Vec<Forecasted>,
+    pub warning: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Report {
+    pub fn new(series: &Series, result: &Result<Vec<Forecast>, HeronError>) -> Report {
+        let mut report = Report {
+            recurrence: series.name.clone(),
+            occurrences: series.events.len(),
+            forecasts: vec![],
+            warning: None,
+            error: None,
+        };
+        match result {
+            Ok(forecasts) => {
+                report.forecasts = forecasts
+                    .iter()
+                    .filter_map(|f| f.best())
+                    .map(|best| Forecasted {
+                        date: best.date.format("%Y-%m-%d").to_string(),
+                        score: best.score,
+                        probability: best.probability,
+                    })
+                    .collect();
+                report.warning = forecasts
+                    .iter()
+                    .find_map(|f| f.fallback)
+                    .map(|f| f.to_string());
+            }
+            Err(e) => report.error = Some(e.to_string()),
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn series(name: &str) -> Series {
+        Series {
+            name: name.to_string(),
+            events: vec![Utc.ymd(2021, 4, 14)],
+        }
+    }
+
+    #[test]
+    fn parse_series_sorts_and_dedups() {
+        let lines: Vec<String> = vec![
+            "seminar,2021-05-12",
+            "meeting,2021-04-20",
+            "seminar,2021-04-14",
+            "seminar,2021-05-12",
+        ]
+        .into_iter()
+        .map(|l| l.to_string())
+        .collect();
+        let series = parse_series(&lines).unwrap();
+
+        assert_eq!(series[0].name, "seminar");
+        assert_eq!(
+            series[0].events,
+            vec![Utc.ymd(2021, 4, 14), Utc.ymd(2021, 5, 12)]
+        );
+        assert_eq!(series[1].events, vec![Utc.ymd(2021, 4, 20)]);
+    }
+
+    #[test]
+    fn panicking_series_becomes_error_row() {
+        let input = vec![series("seminar"), series("broken"), series("meeting")];
+        let results = run(input, 2, |s| {
+            if s.name == "broken" {
+                panic!("index out of bounds");
+            }
+            Ok(s.events.len())
+        });
+
+        let names: Vec<&str> = results.iter().map(|(s, _)| s.name.as_str()).collect();
+        assert_eq!(names, vec!["seminar", "broken", "meeting"]);
+        assert_eq!(results[0].1, Ok(1));
+        assert_eq!(
+            results[1].1,
+            Err(HeronError::Internal("index out of bounds".to_string()))
+        );
+        assert_eq!(results[2].1, Ok(1));
+    }
+}
@@ -0,0 +1,194 @@
+use chrono::prelude::*;
+use chrono::{Date, Duration, Utc};
+use std::fmt;
+use std::sync::Arc;
+
+use super::forecaster::get_params;
+use super::holiday::HolidayProvider;
+use super::method::{Forecaster, Options};
+use super::result::{Candidate, Forecast};
+use crate::error::HeronError;
+
+// この範囲の間隔は 1 年ごとの予定とみなす (日)
+const ANNUAL_INTERVAL: (i64, i64) = (300, 430);
+
+// 履歴が短いときに使った予測の方法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fallback {
+    // 翌年の同じ月の同じ週の同じ曜日
+    SameWeekNextYear,
+    // 直近 2 回の間隔 (日) で繰り返す
+    FixedInterval(i64),
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fallback::SameWeekNextYear => write!(
+                f,
+                "short history: forecast the same weekday of the same week next year"
+            ),
+            Fallback::FixedInterval(d) => {
+                write!(f, "short history: repeat the interval of {} days", d)
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////
+// Same week next year
+//-----------------------------------------
+// date の月の第 n 曜日を翌年の同じ月で求める．
+// 翌年に第 n 曜日がなければ最終曜日にする
+///////////////////////////////////////////
+// Example
+//-----------------------------------------
+// date   : '2020/6/9' (第 2 火曜日)
+// return : '2021/6/8' (第 2 火曜日)
+///////////////////////////////////////////
+pub fn same_week_next_year(date: Date<Utc>) -> Date<Utc> {
+    let nth = (date.day() - 1) / 7;
+    let first = Utc.ymd(date.year() + 1, date.month(), 1);
+    let offset =
+        (7 + date.weekday().num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    let mut next = first + Duration::days((offset + nth * 7) as i64);
+    while next.month() != date.month() {
+        next = next - Duration::days(7);
+    }
+    next
+}
+
+///////////////////////////////////////////
+// Cold-start forecasting
+//-----------------------------------------
+// 周期や回帰に必要なだけの予定がないときの予測
+//
+// 予定 1 つ          : 翌年の同じ週の同じ曜日
+// 予定 2 つ以上      : 直近 2 回の間隔が 1 年程度なら同上，
+//                      そうでなければその間隔で繰り返す
+//
+// 候補日は基準日の前後 range_candidate 日で，
+// 基準日から遠いほどスコアを下げる
+///////////////////////////////////////////
+pub struct ColdStartForecaster {
+    holidays: Arc<dyn HolidayProvider>,
+    range_candidate: Vec<i64>,
+}
+
+impl ColdStartForecaster {
+    pub fn new(options: &Options) -> ColdStartForecaster {
+        ColdStartForecaster {
+            holidays: options.holidays.clone(),
+            range_candidate: vec![0],
+        }
+    }
+}
+
+impl Forecaster for ColdStartForecaster {
+    fn fit(
+        &mut self,
+        _events: &Vec<Date<Utc>>,
+        _range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+    ) -> Result<(), HeronError> {
+        self.range_candidate = range_candidate.clone();
+        Ok(())
+    }
+
+    fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast {
+        let latest = match events.last() {
+            Some(d) => *d,
+            None => return Forecast::new(vec![]),
+        };
+
+        let mut fallback = Fallback::SameWeekNextYear;
+        if events.len() >= 2 {
+            let interval = (latest - events[events.len() - 2]).num_days();
+            if interval > 0 && (interval < ANNUAL_INTERVAL.0 || ANNUAL_INTERVAL.1 < interval) {
+                fallback = Fallback::FixedInterval(interval);
+            }
+        }
+        let pivot = match fallback {
+            Fallback::SameWeekNextYear => same_week_next_year(latest),
+            Fallback::FixedInterval(d) => latest + Duration::days(d),
+        };
+        let period = (pivot - latest).num_days() as usize;
+
+        let candidates = self
+            .range_candidate
+            .iter()
+            .map(|x| {
+                let date = pivot + Duration::days(*x);
+                let score = 1.0 / (1.0 + x.abs() as f64);
+                let mut candidate =
+                    Candidate::new(date, score, get_params(&date, self.holidays.as_ref()));
+                candidate.period = Some(period);
+                candidate.pivot = Some(pivot);
+                candidate
+            })
+            .collect();
+
+        let mut forecast = Forecast::new(candidates);
+        forecast.fallback = Some(fallback);
+        forecast
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::holiday::NoHolidays;
+
+    fn rank(events: Vec<Date<Utc>>) -> Forecast {
+        let mut forecaster = ColdStartForecaster::new(&Options::new(Arc::new(NoHolidays)));
+        forecaster
+            .fit(&events, &vec![], &(-3..4).collect())
+            .unwrap();
+        forecaster.rank(&events)
+    }
+
+    #[test]
+    fn same_week_next_year_keeps_nth_weekday() {
+        assert_eq!(
+            same_week_next_year(Utc.ymd(2020, 6, 9)),
+            Utc.ymd(2021, 6, 8)
+        );
+        assert_eq!(
+            same_week_next_year(Utc.ymd(2020, 12, 31)),
+            Utc.ymd(2021, 12, 30)
+        );
+    }
+
+    #[test]
+    fn same_week_next_year_falls_back_to_last_weekday() {
+        // 2020/9/29 は第 5 火曜日だが 2021 年 9 月には第 5 火曜日がない
+        assert_eq!(
+            same_week_next_year(Utc.ymd(2020, 9, 29)),
+            Utc.ymd(2021, 9, 28)
+        );
+    }
+
+    #[test]
+    fn single_event_repeats_next_year() {
+        let forecast = rank(vec![Utc.ymd(2020, 6, 9)]);
+        assert_eq!(forecast.fallback, Some(Fallback::SameWeekNextYear));
+        assert_eq!(forecast.best().unwrap().date, Utc.ymd(2021, 6, 8));
+        assert_eq!(forecast.candidates.len(), 7);
+    }
+
+    #[test]
+    fn events_a_year_apart_repeat_next_year() {
+        let forecast = rank(vec![Utc.ymd(2019, 6, 11), Utc.ymd(2020, 6, 9)]);
+        assert_eq!(forecast.fallback, Some(Fallback::SameWeekNextYear));
+        assert_eq!(forecast.best().unwrap().date, Utc.ymd(2021, 6, 8));
+    }
+
+    #[test]
+    fn short_interval_repeats_the_interval() {
+        let forecast = rank(vec![Utc.ymd(2020, 5, 12), Utc.ymd(2020, 6, 9)]);
+        assert_eq!(forecast.fallback, Some(Fallback::FixedInterval(28)));
+        let best = forecast.best().unwrap();
+        assert_eq!(best.date, Utc.ymd(2020, 7, 7));
+        assert_eq!(best.period, Some(28));
+    }
+}
@@ -0,0 +1,303 @@
+use chrono::{Date, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use super::feature::DayOfWeek;
+use super::holiday::HolidayProvider;
+use super::json_map;
+use super::method::{Forecaster, Options};
+use super::result::Forecast;
+use crate::error::HeronError;
+
+///////////////////////////////////////////
+// Constraints on candidates
+//-----------------------------------------
+// no_holidays : 祝日には行わない
+// weekdays    : 行ってよい曜日 (None なら全曜日，空なら行えない)
+// blackouts   : 行わない期間 (両端を含む)
+// busy        : 既に予定が入っている日
+///////////////////////////////////////////
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Constraints {
+    #[serde(default)]
+    pub no_holidays: bool,
+    #[serde(default)]
+    pub weekdays: Option<Vec<DayOfWeek>>,
+    #[serde(default)]
+    pub blackouts: Vec<Blackout>,
+    #[serde(default, with = "ymd_list")]
+    pub busy: Vec<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Blackout {
+    #[serde(with = "ymd")]
+    pub from: NaiveDate,
+    #[serde(with = "ymd")]
+    pub to: NaiveDate,
+}
+
+impl Blackout {
+    // "YYYY-MM-DD:YYYY-MM-DD" または 1 日だけの "YYYY-MM-DD"
+    pub fn parse(s: &str) -> Option<Blackout> {
+        let mut dates = s.splitn(2, ':');
+        let from = NaiveDate::parse_from_str(dates.next()?.trim(), "%Y-%m-%d").ok()?;
+        let to = match dates.next() {
+            Some(d) => NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok()?,
+            None => from,
+        };
+        if to < from {
+            return None;
+        }
+        Some(Blackout { from, to })
+    }
+}
+
+// 制約に反した理由
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Violation {
+    Holiday,
+    Weekday(DayOfWeek),
+    Blackout(Blackout),
+    Busy,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Holiday => write!(f, "holiday"),
+            Violation::Weekday(w) => write!(f, "{:?} is not allowed", w),
+            Violation::Blackout(b) => write!(f, "blackout {}..{}", b.from, b.to),
+            Violation::Busy => write!(f, "busy"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rejection {
+    pub date: Date<Utc>,
+    pub violation: Violation,
+}
+
+impl Constraints {
+    pub fn is_empty(&self) -> bool {
+        *self == Constraints::default()
+    }
+
+    // 両方の制約を満たすもの (曜日は共通部分．共通の曜日がなければ空)
+    pub fn merge(&self, other: &Constraints) -> Constraints {
+        let weekdays = match (&self.weekdays, &other.weekdays) {
+            (None, w) | (w, None) => w.clone(),
+            (Some(a), Some(b)) => Some(a.iter().filter(|w| b.contains(w)).cloned().collect()),
+        };
+        Constraints {
+            no_holidays: self.no_holidays || other.no_holidays,
+            weekdays,
+            blackouts: [&self.blackouts[..], &other.blackouts[..]].concat(),
+            busy: [&self.busy[..], &other.busy[..]].concat(),
+        }
+    }
+
+    // 最初に反した制約 (満たしていれば None)
+    pub fn check(&self, date: &Date<Utc>, holidays: &dyn HolidayProvider) -> Option<Violation> {
+        let day = date.naive_utc();
+        if self.no_holidays && holidays.is_holiday(&day) {
+            return Some(Violation::Holiday);
+        }
+        let weekday = DayOfWeek::from(date.weekday());
+        if let Some(weekdays) = &self.weekdays {
+            if !weekdays.contains(&weekday) {
+                return Some(Violation::Weekday(weekday));
+            }
+        }
+        if let Some(b) = self.blackouts.iter().find(|b| b.from <= day && day <= b.to) {
+            return Some(Violation::Blackout(*b));
+        }
+        if self.busy.contains(&day) {
+            return Some(Violation::Busy);
+        }
+        None
+    }
+}
+
+// 全ての候補日が制約に反したときの説明
+pub fn report(rejected: &Vec<Rejection>) -> String {
+    let reasons: Vec<String> = rejected
+        .iter()
+        .map(|r| format!("{} ({})", r.date.format("%Y-%m-%d"), r.violation))
+        .collect();
+    format!(
+        "No candidate satisfies the constraints: {}",
+        reasons.join(", ")
+    )
+}
+
+///////////////////////////////////////////
+// Constrained forecasting
+//-----------------------------------------
+// 元の手法の候補日から制約に反するものを除いて
+// 順位を振り直す．除いた候補日と理由は
+// Forecast::rejected に残す
+///////////////////////////////////////////
+pub struct ConstrainedForecaster {
+    base: Box<dyn Forecaster>,
+    constraints: Constraints,
+    holidays: Arc<dyn HolidayProvider>,
+}
+
+impl ConstrainedForecaster {
+    pub fn new(
+        base: Box<dyn Forecaster>,
+        constraints: Constraints,
+        options: &Options,
+    ) -> ConstrainedForecaster {
+        ConstrainedForecaster {
+            base,
+            constraints,
+            holidays: options.holidays.clone(),
+        }
+    }
+}
+
+impl Forecaster for ConstrainedForecaster {
+    fn fit(
+        &mut self,
+        events: &Vec<Date<Utc>>,
+        range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+    ) -> Result<(), HeronError> {
+        self.base.fit(events, range_recurrence, range_candidate)
+    }
+
+    fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast {
+        let base = self.base.rank(events);
+        let mut candidates = vec![];
+        let mut rejected = vec![];
+        for candidate in base.candidates {
+            match self
+                .constraints
+                .check(&candidate.date, self.holidays.as_ref())
+            {
+                Some(violation) => rejected.push(Rejection {
+                    date: candidate.date,
+                    violation,
+                }),
+                None => candidates.push(candidate),
+            }
+        }
+
+        let mut forecast = Forecast::new(candidates);
+        forecast.periods = base.periods;
+        forecast.intercept = base.intercept;
+        forecast.fallback = base.fallback;
+        forecast.rejected = rejected;
+        forecast
+    }
+}
+
+///////////////////////////////////////////
+// Constraints file
+//-----------------------------------------
+// recurrence_name => Constraints の JSON
+// ファイルがなければ空とみなす
+///////////////////////////////////////////
+// Example
+//-----------------------------------------
+// {
+//   "seminar": {
+//     "no_holidays": true,
+//     "weekdays": ["Mon", "Tue", "Wed", "Thu", "Fri"],
+//     "blackouts": [{ "from": "2021-12-28", "to": "2022-01-04" }],
+//     "busy": ["2021-05-06"]
+//   }
+// }
+///////////////////////////////////////////
+pub fn load(path: &str) -> io::Result<BTreeMap<String, Constraints>> {
+    json_map::load(path)
+}
+
+// "YYYY-MM-DD" 形式の日付
+mod ymd {
+    use chrono::NaiveDate;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(date: &NaiveDate, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&date.format("%Y-%m-%d").to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveDate, D::Error> {
+        let s = String::deserialize(d)?;
+        NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(de::Error::custom)
+    }
+}
+
+mod ymd_list {
+    use chrono::NaiveDate;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(dates: &Vec<NaiveDate>, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(dates.iter().map(|d| d.format("%Y-%m-%d").to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<NaiveDate>, D::Error> {
+        Vec::<String>::deserialize(d)?
+            .iter()
+            .map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::holiday::NoHolidays;
+    use chrono::TimeZone;
+
+    fn weekdays(days: &[DayOfWeek]) -> Constraints {
+        Constraints {
+            weekdays: Some(days.to_vec()),
+            ..Constraints::default()
+        }
+    }
+
+    #[test]
+    fn merge_intersects_weekdays() {
+        let merged = weekdays(&[DayOfWeek::Mon, DayOfWeek::Wed])
+            .merge(&weekdays(&[DayOfWeek::Wed, DayOfWeek::Fri]));
+        assert_eq!(merged.weekdays, Some(vec![DayOfWeek::Wed]));
+    }
+
+    #[test]
+    fn merge_keeps_one_sided_weekdays() {
+        let merged = Constraints::default().merge(&weekdays(&[DayOfWeek::Tue]));
+        assert_eq!(merged.weekdays, Some(vec![DayOfWeek::Tue]));
+        assert_eq!(
+            Constraints::default()
+                .merge(&Constraints::default())
+                .weekdays,
+            None
+        );
+    }
+
+    #[test]
+    fn disjoint_merge_rejects_every_day() {
+        let merged = weekdays(&[DayOfWeek::Mon]).merge(&weekdays(&[DayOfWeek::Fri]));
+        assert_eq!(merged.weekdays, Some(vec![]));
+        // 2021-05-03 (月) から 1 週間
+        for d in 3..10 {
+            let date = Utc.ymd(2021, 5, d);
+            assert!(merged.check(&date, &NoHolidays).is_some(), "{}", date);
+        }
+    }
+
+    #[test]
+    fn unrestricted_weekdays_accept_every_day() {
+        let constraints = Constraints::default();
+        for d in 3..10 {
+            assert_eq!(constraints.check(&Utc.ymd(2021, 5, d), &NoHolidays), None);
+        }
+    }
+}
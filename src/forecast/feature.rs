@@ -0,0 +1,170 @@
+use chrono::Weekday;
+use serde::{Deserialize, Serialize};
+
+///////////////////////////////////////////
+// Calendar feature
+//-----------------------------------------
+// 学習・比較には値そのものを使い，
+// 文字列にするのは出力時 (label) のみ
+///////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DayOfWeek {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl From<Weekday> for DayOfWeek {
+    fn from(weekday: Weekday) -> DayOfWeek {
+        match weekday {
+            Weekday::Mon => DayOfWeek::Mon,
+            Weekday::Tue => DayOfWeek::Tue,
+            Weekday::Wed => DayOfWeek::Wed,
+            Weekday::Thu => DayOfWeek::Thu,
+            Weekday::Fri => DayOfWeek::Fri,
+            Weekday::Sat => DayOfWeek::Sat,
+            Weekday::Sun => DayOfWeek::Sun,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Feature {
+    // 祝日でない日の曜日
+    Weekday(DayOfWeek),
+    Holiday,
+    // 月と月内の週 (1 日から 7 日ごと)
+    MonthWeek { month: u32, week: u32 },
+    Month(u32),
+    MonthDay(u32),
+    // 第 nth weekday
+    NthWeekday { nth: u32, weekday: DayOfWeek },
+    LastWeekday(DayOfWeek),
+    NotLastWeekday,
+    // 翌日から月末までの営業日数
+    BusinessDaysToMonthEnd(u32),
+    // 他の年度の予定と年度内の位置が揃っている度合い (数値の列)
+    AnnualAlignment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Locale {
+    Ja,
+    En,
+}
+
+impl Locale {
+    pub fn parse(s: &str) -> Option<Locale> {
+        match s {
+            "ja" => Some(Locale::Ja),
+            "en" => Some(Locale::En),
+            _ => None,
+        }
+    }
+}
+
+impl DayOfWeek {
+    // "mon" や "Monday" など (大文字小文字は区別しない)
+    pub fn parse(s: &str) -> Option<DayOfWeek> {
+        let s = s.trim().to_lowercase();
+        let weekday = match s.get(..3)? {
+            "mon" => DayOfWeek::Mon,
+            "tue" => DayOfWeek::Tue,
+            "wed" => DayOfWeek::Wed,
+            "thu" => DayOfWeek::Thu,
+            "fri" => DayOfWeek::Fri,
+            "sat" => DayOfWeek::Sat,
+            "sun" => DayOfWeek::Sun,
+            _ => return None,
+        };
+        Some(weekday)
+    }
+
+    pub fn label(&self, locale: Locale) -> String {
+        let (ja, en) = match self {
+            DayOfWeek::Mon => ("月曜日", "Monday"),
+            DayOfWeek::Tue => ("火曜日", "Tuesday"),
+            DayOfWeek::Wed => ("水曜日", "Wednesday"),
+            DayOfWeek::Thu => ("木曜日", "Thursday"),
+            DayOfWeek::Fri => ("金曜日", "Friday"),
+            DayOfWeek::Sat => ("土曜日", "Saturday"),
+            DayOfWeek::Sun => ("日曜日", "Sunday"),
+        };
+        match locale {
+            Locale::Ja => ja.to_string(),
+            Locale::En => en.to_string(),
+        }
+    }
+}
+
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (1, r) if r != 11 => "st",
+        (2, r) if r != 12 => "nd",
+        (3, r) if r != 13 => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+const MONTHS_EN: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+fn month_en(month: u32) -> &'static str {
+    MONTHS_EN[(month as usize + 11) % 12]
+}
+
+impl Feature {
+    pub fn label(&self, locale: Locale) -> String {
+        match locale {
+            Locale::Ja => match self {
+                Feature::Weekday(w) => w.label(locale),
+                Feature::Holiday => "祝日".to_string(),
+                Feature::MonthWeek { month, week } => format!("{}月{}w", month, week),
+                Feature::Month(m) => format!("{}月", m),
+                Feature::MonthDay(d) => format!("{}日", d),
+                Feature::NthWeekday { nth, weekday } => {
+                    format!("第{}{}", nth, weekday.label(locale))
+                }
+                Feature::LastWeekday(w) => format!("最終{}", w.label(locale)),
+                Feature::NotLastWeekday => "最終以外".to_string(),
+                Feature::BusinessDaysToMonthEnd(n) => format!("月末まで{}営業日", n),
+                Feature::AnnualAlignment => "年度内の位置".to_string(),
+            },
+            Locale::En => match self {
+                Feature::Weekday(w) => w.label(locale),
+                Feature::Holiday => "holiday".to_string(),
+                Feature::MonthWeek { month, week } => {
+                    format!("{} week {}", month_en(*month), week)
+                }
+                Feature::Month(m) => month_en(*m).to_string(),
+                Feature::MonthDay(d) => format!("{} of month", ordinal(*d)),
+                Feature::NthWeekday { nth, weekday } => {
+                    format!("{} {}", ordinal(*nth), weekday.label(locale))
+                }
+                Feature::LastWeekday(w) => format!("last {}", w.label(locale)),
+                Feature::NotLastWeekday => "not last weekday".to_string(),
+                Feature::BusinessDaysToMonthEnd(n) => {
+                    format!("{} business days to month end", n)
+                }
+                Feature::AnnualAlignment => "position in fiscal year".to_string(),
+            },
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use nalgebra::Complex;
+use std::f64::consts::PI;
+
+///////////////////////////////////////////
+// Radix-2 FFT (in place)
+//-----------------------------------------
+// buf     : 長さは 2 のべき乗
+// inverse : true なら逆変換 (1/n 倍まで行う)
+///////////////////////////////////////////
+pub fn fft(buf: &mut Vec<Complex<f64>>, inverse: bool) {
+    let n = buf.len();
+
+    // ビット反転順に並べ替え
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j ^= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        let twiddles: Vec<Complex<f64>> = (0..half)
+            .map(|k| {
+                let angle = sign * 2.0 * PI * k as f64 / len as f64;
+                Complex::new(angle.cos(), angle.sin())
+            })
+            .collect();
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                let u = buf[start + k];
+                let v = buf[start + k + half] * twiddles[k];
+                buf[start + k] = u + v;
+                buf[start + k + half] = u - v;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for c in buf.iter_mut() {
+            *c = *c / n as f64;
+        }
+    }
+}
+
+///////////////////////////////////////////
+// Autocorrelation (Wiener–Khinchin)
+//-----------------------------------------
+// f      : 0/1 の発生系列
+// return : r[lag] = Σ f[i] * f[i + lag]
+///////////////////////////////////////////
+pub fn autocorrelation(f: &Vec<f64>) -> Vec<f64> {
+    let n = f.len();
+    // 循環相関にならないよう 2n 以上に 0 詰めする
+    let size = (2 * n).next_power_of_two();
+    let mut buf: Vec<Complex<f64>> = f.iter().map(|x| Complex::new(*x, 0.0)).collect();
+    buf.resize(size, Complex::new(0.0, 0.0));
+
+    fft(&mut buf, false);
+    for c in buf.iter_mut() {
+        *c = Complex::new(c.norm_sqr(), 0.0);
+    }
+    fft(&mut buf, true);
+
+    // 0/1 系列の積和は整数なので丸め誤差を落とす
+    buf[..n].iter().map(|c| c.re.round()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 以前の O(n^2) の計算
+    fn naive_autocorrelation(f: &Vec<f64>) -> Vec<f64> {
+        let n = f.len();
+        (0..n)
+            .map(|lag| (0..n - lag).map(|i| f[i] * f[i + lag]).sum())
+            .collect()
+    }
+
+    // 再現できる 0/1 系列 (線形合同法)
+    fn occurrences(n: usize, seed: u64) -> Vec<f64> {
+        let mut x = seed;
+        (0..n)
+            .map(|_| {
+                x = x
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                if (x >> 33) % 5 == 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn autocorrelation_matches_naive() {
+        for &n in [1, 2, 7, 64, 365, 1000].iter() {
+            let f = occurrences(n, n as u64);
+            assert_eq!(autocorrelation(&f), naive_autocorrelation(&f), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn inverse_restores_input() {
+        let input: Vec<Complex<f64>> = occurrences(16, 3)
+            .into_iter()
+            .map(|x| Complex::new(x, 0.0))
+            .collect();
+        let mut buf = input.clone();
+        fft(&mut buf, false);
+        fft(&mut buf, true);
+        for (a, b) in buf.iter().zip(input.iter()) {
+            assert!((a - b).norm_sqr() < 1e-18);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::prelude::*;
+use chrono::{Date, NaiveDate, Utc};
+
+///////////////////////////////////////////
+// Fiscal year
+//-----------------------------------------
+// month, day : 年度の開始日 (デフォルトは 4 月 1 日)
+// 年度 y は (y, month, day) から始まる
+///////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FiscalYear {
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Default for FiscalYear {
+    fn default() -> FiscalYear {
+        FiscalYear { month: 4, day: 1 }
+    }
+}
+
+impl FiscalYear {
+    // 閏年にしか存在しない 2 月 29 日は不可
+    pub fn new(month: u32, day: u32) -> Option<FiscalYear> {
+        NaiveDate::from_ymd_opt(2001, month, day)?;
+        Some(FiscalYear { month, day })
+    }
+
+    // "MM-DD" または "MM"
+    pub fn parse(s: &str) -> Option<FiscalYear> {
+        let mut parts = s.trim().split('-');
+        let month = parts.next()?.parse::<u32>().ok()?;
+        let day = match parts.next() {
+            Some(d) => d.parse::<u32>().ok()?,
+            None => 1,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        FiscalYear::new(month, day)
+    }
+
+    pub fn start(&self, year: i32) -> Date<Utc> {
+        Utc.ymd(year, self.month, self.day)
+    }
+
+    // date を含む年度
+    pub fn year_of(&self, date: Date<Utc>) -> i32 {
+        if date < self.start(date.year()) {
+            return date.year() - 1;
+        }
+        date.year()
+    }
+
+    pub fn first_date(&self, date: Date<Utc>) -> Date<Utc> {
+        self.start(self.year_of(date))
+    }
+
+    // 年度内の相対的な位置 (年度初めが 0，次の年度初めが 1)
+    pub fn position(&self, date: Date<Utc>) -> f64 {
+        let year = self.year_of(date);
+        let len = (self.start(year + 1) - self.start(year)).num_days();
+        (date - self.start(year)).num_days() as f64 / len as f64
+    }
+
+    ///////////////////////////////////////////
+    // Default sampling range
+    //-----------------------------------------
+    // events : occurrence history (日付順)
+    // years  : 直近何年度分を使うか (None なら全履歴)
+    // return : [最初の年度初め, 最後の予定]
+    ///////////////////////////////////////////
+    pub fn sampling_range(&self, events: &Vec<Date<Utc>>, years: Option<u32>) -> Vec<Date<Utc>> {
+        let last = *events.last().unwrap();
+        let mut first = self.first_date(events[0]);
+        if let Some(y) = years {
+            let recent = self.start(self.year_of(last) - y as i32 + 1);
+            if first < recent {
+                first = recent;
+            }
+        }
+        vec![first, last]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fy(month: u32) -> FiscalYear {
+        FiscalYear::new(month, 1).unwrap()
+    }
+
+    #[test]
+    fn start_of_january_april_october_years() {
+        assert_eq!(fy(1).start(2021), Utc.ymd(2021, 1, 1));
+        assert_eq!(fy(4).start(2021), Utc.ymd(2021, 4, 1));
+        assert_eq!(fy(10).start(2021), Utc.ymd(2021, 10, 1));
+    }
+
+    #[test]
+    fn year_of_depends_on_start() {
+        let date = Utc.ymd(2021, 3, 15);
+        assert_eq!(fy(1).year_of(date), 2021);
+        assert_eq!(fy(4).year_of(date), 2020);
+        assert_eq!(fy(10).year_of(date), 2020);
+        assert_eq!(fy(10).year_of(Utc.ymd(2021, 10, 1)), 2021);
+    }
+
+    #[test]
+    fn sampling_range_starts_at_first_fiscal_year() {
+        let events = vec![
+            Utc.ymd(2019, 2, 13),
+            Utc.ymd(2019, 11, 13),
+            Utc.ymd(2021, 3, 10),
+        ];
+        let last = Utc.ymd(2021, 3, 10);
+        assert_eq!(
+            fy(1).sampling_range(&events, None),
+            vec![Utc.ymd(2019, 1, 1), last]
+        );
+        assert_eq!(
+            fy(4).sampling_range(&events, None),
+            vec![Utc.ymd(2018, 4, 1), last]
+        );
+        assert_eq!(
+            fy(10).sampling_range(&events, None),
+            vec![Utc.ymd(2018, 10, 1), last]
+        );
+    }
+
+    #[test]
+    fn sampling_range_keeps_recent_years() {
+        let events = vec![
+            Utc.ymd(2019, 2, 13),
+            Utc.ymd(2019, 11, 13),
+            Utc.ymd(2021, 3, 10),
+        ];
+        let last = Utc.ymd(2021, 3, 10);
+        // 2021-03-10 は 1 月始まりなら 2021 年度，4 月・10 月始まりなら 2020 年度
+        assert_eq!(
+            fy(1).sampling_range(&events, Some(1)),
+            vec![Utc.ymd(2021, 1, 1), last]
+        );
+        assert_eq!(
+            fy(4).sampling_range(&events, Some(2)),
+            vec![Utc.ymd(2019, 4, 1), last]
+        );
+        assert_eq!(
+            fy(10).sampling_range(&events, Some(2)),
+            vec![Utc.ymd(2019, 10, 1), last]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_start() {
+        assert_eq!(FiscalYear::parse("10"), Some(fy(10)));
+        assert_eq!(FiscalYear::parse("04-01"), Some(fy(4)));
+        assert_eq!(FiscalYear::parse("02-29"), None);
+        assert_eq!(FiscalYear::parse("13-01"), None);
+    }
+}
@@ -1,146 +1,115 @@
 use chrono::prelude::*;
 use chrono::{Date, Duration, Utc, Weekday};
-use jpholiday::jpholiday::JPHoliday;
-use nalgebra::{DMatrix, DVector, RowDVector};
-use polars::prelude::*;
-use smartcore::linear::linear_regression::*;
-
-fn weekdays(date: &Date<Utc>) -> String {
-    let jpholiday = JPHoliday::new();
-    if jpholiday.is_holiday(&date.naive_utc()) {
-        return "祝日".to_string();
+use nalgebra::{DMatrix, DVector};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::feature::Feature;
+use super::fiscal_year::FiscalYear;
+use super::holiday::HolidayProvider;
+use super::method::{Forecaster, Options};
+use super::periodicity::{self, Period};
+use super::result::{Candidate, Contribution, Forecast};
+use crate::error::HeronError;
+
+fn weekdays(date: &Date<Utc>, holidays: &dyn HolidayProvider) -> Feature {
+    if holidays.is_holiday(&date.naive_utc()) {
+        return Feature::Holiday;
     }
-    match date.weekday() {
-        Weekday::Mon => return "月曜日".to_string(),
-        Weekday::Tue => return "火曜日".to_string(),
-        Weekday::Wed => return "水曜日".to_string(),
-        Weekday::Thu => return "木曜日".to_string(),
-        Weekday::Fri => return "金曜日".to_string(),
-        Weekday::Sat => return "土曜日".to_string(),
-        Weekday::Sun => return "日曜日".to_string(),
+    Feature::Weekday(date.weekday().into())
+}
+
+fn monthweek(date: &Date<Utc>) -> Feature {
+    Feature::MonthWeek {
+        month: date.month(),
+        week: (date.day() - 1) / 7 + 1,
     }
 }
 
-fn weekdays_considering_nholiday(dates: &Vec<Date<Utc>>) -> Vec<String> {
-    dates.iter().map(|date| weekdays(&date)).collect()
+fn month(date: &Date<Utc>) -> Feature {
+    Feature::Month(date.month())
 }
 
-fn monthweek(date: &Date<Utc>) -> String {
-    let month = date.month().to_string() + "月";
-    let week = ((date.day() - 1) / 7 + 1).to_string() + "w";
-    month + &week
+fn monthday(date: &Date<Utc>) -> Feature {
+    Feature::MonthDay(date.day())
 }
 
-fn monthweeks(dates: &Vec<Date<Utc>>) -> Vec<String> {
-    dates.iter().map(|date| monthweek(&date)).collect()
+// 第 3 火曜日
+fn nth_weekday(date: &Date<Utc>) -> Feature {
+    Feature::NthWeekday {
+        nth: (date.day() - 1) / 7 + 1,
+        weekday: date.weekday().into(),
+    }
+}
+
+// 最終金曜日
+fn last_weekday(date: &Date<Utc>) -> Feature {
+    if (*date + Duration::days(7)).month() != date.month() {
+        return Feature::LastWeekday(date.weekday().into());
+    }
+    Feature::NotLastWeekday
+}
+
+fn is_business_day(date: &Date<Utc>, holidays: &dyn HolidayProvider) -> bool {
+    match date.weekday() {
+        Weekday::Sat | Weekday::Sun => false,
+        _ => !holidays.is_holiday(&date.naive_utc()),
+    }
 }
 
-fn month(date: &Date<Utc>) -> String {
-    date.month().to_string() + "月"
+// 翌日から月末までの営業日数 (0 なら月末最終営業日以降)
+fn business_days_to_month_end(date: &Date<Utc>, holidays: &dyn HolidayProvider) -> Feature {
+    let mut count = 0;
+    let mut d = date.succ();
+    while d.month() == date.month() {
+        if is_business_day(&d, holidays) {
+            count += 1;
+        }
+        d = d.succ();
+    }
+    Feature::BusinessDaysToMonthEnd(count)
 }
 
-fn months(dates: &Vec<Date<Utc>>) -> Vec<String> {
-    dates.iter().map(|date| month(&date)).collect()
+// get_params_list の 1 行分
+pub fn get_params(date: &Date<Utc>, holidays: &dyn HolidayProvider) -> Vec<Feature> {
+    vec![
+        weekdays(date, holidays),
+        monthweek(date),
+        month(date),
+        monthday(date),
+        nth_weekday(date),
+        last_weekday(date),
+        business_days_to_month_end(date, holidays),
+    ]
 }
 
 ///////////////////////////////////////////
 // Generate parameters list
 //-----------------------------------------
-// dates  : date of vector
-// return : list of parameters
+// dates    : date of vector
+// holidays : 祝日の判定
+// return   : list of parameters (1 日 1 行)
 ///////////////////////////////////////////
 // Example
 //-----------------------------------------
 // dates  : vec!['2013/4/2', '2013/4/3', ...]
 // return :
-//           date  wday   week month monthday
-//    ('2013/4/2',  Wed, 4月1w,  4月,       2
-//     '2013/4/3',  Thu, 4月1w,  4月,       3
+//           date  wday   week month monthday  nthwday   lastwday  to_month_end
+//    ('2013/4/2',  Tue, 4月1w,  4月,      2日, 第1火曜日, 最終以外, 月末まで19営業日
+//     '2013/4/3',  Wed, 4月1w,  4月,      3日, 第1水曜日, 最終以外, 月末まで18営業日
 //      ...
 ///////////////////////////////////////////
-fn get_params_list(dates: &Vec<Date<Utc>>) -> DataFrame {
-    let wdays = weekdays_considering_nholiday(dates);
-    let weeks = monthweeks(dates);
-    let months = months(dates);
-    // let holidays = holidays(&dates);
-    //
-    let plist = df!("wday" => &wdays,
-		    "weeks" => &weeks,
-		    "months" => &months)
-    .unwrap();
-
-    plist
-}
-
-fn dates_to_occurreds(dates: &Vec<Date<Utc>>, range: &Vec<Date<Utc>>) -> Vec<f64> {
-    let len = (range[1] - range[0]).num_days() + 1;
-    let mut occurreds = vec![0.0; len as usize];
-
-    let seq_dates: Vec<Date<Utc>> = (0..len).map(|x| range[0] + Duration::days(x)).collect();
-
-    for date in dates.iter() {
-        for (j, seq_date) in seq_dates.iter().enumerate() {
-            if date == seq_date {
-                occurreds[j] = 1.0;
-            }
-        }
-    }
-    occurreds
-}
-
-fn mean(v: &Vec<f64>) -> f64 {
-    let mut sum: f64 = 0.0;
-    for i in v {
-        sum += *i;
-    }
-    sum / v.len() as f64
-}
-
-fn get_ac(f: &Vec<f64>, range: &Vec<Date<Utc>>) -> Vec<f64> {
-    // let start = 0;
-    let end = (range[1] - range[0]).num_days() + 1;
-
-    let mut ac: Vec<f64> = vec![0.0; (end + 1) as usize];
-
-    for lag in 0..end {
-        let mut p = Vec::new();
-        // f[lag] * f[0], f[lag + 1] * f[1], ..., f[f.len()] * f[f.len()]
-        // f[lag] * f[0], f[lag + 1] * f[1], ..., f[f.len()] * f[f.len() - lag]
-        for i in 0..(f.len() as i64 - lag) {
-            p.push(f[(lag + i) as usize] * f[i as usize]);
-        }
-        if lag != 0 {
-            ac[(lag - 1) as usize] = mean(&p);
-        }
-    }
-
-    ac
-}
-
-fn get_big_wave_cycle(dates: &Vec<Date<Utc>>, range: &Vec<Date<Utc>>) -> usize {
-    let series = dates_to_occurreds(dates, range);
-    let mut ac = get_ac(&series, range);
-    let mut max = 0.0;
-    let mut max_index = 0;
-
-    // 要修正
-    // 長過ぎる周期をカット
-    if ac.len() > 400 {
-        ac = ac[..400].to_vec();
-    }
-    for (i, &val) in ac.iter().enumerate() {
-        if max < val {
-            max = val;
-            max_index = i + 1;
-        }
-    }
-    max_index
+fn get_params_list(dates: &Vec<Date<Utc>>, holidays: &dyn HolidayProvider) -> Vec<Vec<Feature>> {
+    dates
+        .iter()
+        .map(|date| get_params(date, holidays))
+        .collect()
 }
 
 fn closest_event_index(events: &Vec<Date<Utc>>, date: Date<Utc>) -> usize {
     let last = events.len() - 1;
     let mut index: usize = 0;
-    for i in 0..=last {
+    for i in 0..last {
         if events[i] <= date && date <= events[i + 1] {
             if (date - events[i]).num_days() < (events[i + 1] - date).num_days() {
                 index = i;
@@ -157,185 +126,827 @@ fn closest_event_index(events: &Vec<Date<Utc>>, date: Date<Utc>) -> usize {
     index
 }
 
-fn get_candidates(events: &Vec<Date<Utc>>, range: &Vec<i64>, period: usize) -> Vec<Date<Utc>> {
+// return : (基準日, 候補日)
+fn get_candidates(
+    events: &Vec<Date<Utc>>,
+    range: &Vec<i64>,
+    period: usize,
+) -> (Date<Utc>, Vec<Date<Utc>>) {
     let latest = events.last().unwrap();
     let criterion = *latest - Duration::days(period as i64);
-    let i = closest_event_index(events, criterion);
-    let mut d = (events[i + 1] - events[i]).num_days();
+    // 予定が 1 つしかなければ周期をそのまま使う
+    let mut d = period as i64;
+    if events.len() >= 2 {
+        // 短い周期では直近の予定が最も近くなるので，その 1 つ前との間隔を使う
+        let i = closest_event_index(events, criterion).min(events.len() - 2);
+        d = (events[i + 1] - events[i]).num_days();
+    }
     if d > 365 {
         d = 365;
     }
     let pivot = *latest + Duration::days(d);
     let candidates: Vec<Date<Utc>> = range.iter().map(|x| pivot + Duration::days(*x)).collect();
 
+    (pivot, candidates)
+}
+
+// 各周期から求めた候補日の和集合 (日付順)
+// 複数の周期から得られた候補日は，強い方の周期と基準日を残す
+fn get_candidates_all(
+    events: &Vec<Date<Utc>>,
+    range: &Vec<i64>,
+    periods: &Vec<Period>,
+) -> Vec<(Date<Utc>, usize, Date<Utc>)> {
+    let mut candidates: Vec<(Date<Utc>, usize, Date<Utc>)> = vec![];
+    for period in periods.iter() {
+        let (pivot, dates) = get_candidates(events, range, period.days);
+        for date in dates {
+            if candidates.iter().all(|c| c.0 != date) {
+                candidates.push((date, period.days, pivot));
+            }
+        }
+    }
+    candidates.sort_by_key(|c| c.0);
+
     candidates
 }
 
-fn gen_lm(cdv: &Series) -> (Vec<String>, Vec<f64>) {
-    let nrow = cdv.len();
-    let mut col_uniq: Vec<String> = vec![];
-    col_uniq.push(cdv.get(0).to_string());
-    for i in 0..cdv.len() {
-        let mut flag = true;
-        for col in col_uniq.iter() {
-            if &cdv.get(i).to_string() == col {
-                flag = false;
-                break;
+///////////////////////////////////////////
+// Design matrix
+//-----------------------------------------
+// one-hot 行列を密に持つ代わりに，各日について
+// 0 でない列番号と値だけを持つ
+//
+// cols : 列の特徴量 (種類ごとに出現順，数値の列は最後)
+// rows : 各日の (列番号, 値)
+///////////////////////////////////////////
+struct Design {
+    cols: Vec<Feature>,
+    index: HashMap<Feature, usize>,
+    rows: Vec<Vec<(usize, f64)>>,
+}
+
+impl Design {
+    fn new(plist: &Vec<Vec<Feature>>) -> Design {
+        let mut cols: Vec<Feature> = vec![];
+        let mut index: HashMap<Feature, usize> = HashMap::new();
+        let mut rows: Vec<Vec<(usize, f64)>> = vec![vec![]; plist.len()];
+
+        let nfeature = plist.first().map_or(0, |params| params.len());
+        for k in 0..nfeature {
+            for (row, params) in rows.iter_mut().zip(plist.iter()) {
+                let feature = params[k];
+                let col = match index.get(&feature) {
+                    Some(col) => *col,
+                    None => {
+                        cols.push(feature);
+                        index.insert(feature, cols.len() - 1);
+                        cols.len() - 1
+                    }
+                };
+                row.push((col, 1.0));
             }
         }
-        if flag {
-            col_uniq.push(cdv.get(i).to_string());
+
+        Design { cols, index, rows }
+    }
+
+    // 各日の値を持つ数値の列を加える
+    fn push_column(&mut self, feature: Feature, values: &Vec<f64>) {
+        self.cols.push(feature);
+        self.index.insert(feature, self.cols.len() - 1);
+        for (row, v) in self.rows.iter_mut().zip(values.iter()) {
+            row.push((self.cols.len() - 1, *v));
         }
     }
-    let ncol = col_uniq.len();
 
-    // let mut m: Array2<f64> = Array::zeros((nrow, ncol));
+    // 学習期間に現れなかった特徴量は無視する
+    fn encode(&self, params: &Vec<Feature>) -> Vec<(usize, f64)> {
+        params
+            .iter()
+            .filter_map(|feature| self.index.get(feature).map(|col| (*col, 1.0)))
+            .collect()
+    }
 
-    let mut vec: Vec<f64> = vec![0.0; nrow * ncol];
-    for row in 0..nrow {
-        for col in 0..ncol {
-            if cdv.get(row).to_string() == col_uniq[col] {
-                // m[[row, col]] = 1.0;
-                vec[col * nrow + row] = 1.0;
+    fn ncols(&self) -> usize {
+        self.cols.len()
+    }
+
+    // 切片を最後の列に加えた Σ w_i x_i x_i^T
+    fn gram(&self, weights: &Vec<f64>) -> DMatrix<f64> {
+        let p = self.ncols();
+        let mut g = DMatrix::<f64>::zeros(p + 1, p + 1);
+        for (row, w) in self.rows.iter().zip(weights.iter()) {
+            for (a, xa) in row.iter().cloned().chain(Some((p, 1.0))) {
+                for (b, xb) in row.iter().cloned().chain(Some((p, 1.0))) {
+                    g[(a, b)] += w * xa * xb;
+                }
+            }
+        }
+        g
+    }
+
+    // 切片を最後の列に加えた Σ v_i x_i
+    fn xtv(&self, v: &Vec<f64>) -> DVector<f64> {
+        let p = self.ncols();
+        let mut xv = DVector::<f64>::zeros(p + 1);
+        for (row, val) in self.rows.iter().zip(v.iter()) {
+            for (a, xa) in row.iter().cloned().chain(Some((p, 1.0))) {
+                xv[a] += val * xa;
             }
         }
+        xv
+    }
+
+    // 切片を最後の要素に持つ係数での線形予測子
+    fn predict(&self, beta: &DVector<f64>) -> Vec<f64> {
+        let p = self.ncols();
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(|(a, x)| beta[*a] * x).sum::<f64>() + beta[p])
+            .collect()
     }
-    return (col_uniq, vec);
 }
 
-fn get_lm_all(first: Date<Utc>, last: Date<Utc>) -> (DMatrix<f64>, Vec<String>) {
+fn get_lm_all(first: Date<Utc>, last: Date<Utc>, holidays: &dyn HolidayProvider) -> Design {
     let len = (last - first).num_days();
     let dates: Vec<Date<Utc>> = (0..=len).map(|x| first + Duration::days(x)).collect();
-    let plist_alldate = get_params_list(&dates);
-    let cols = plist_alldate.get_columns();
-
-    let (mut col_uniq, mut lms) = gen_lm(&cols[0]);
-    if cols.len() > 1 {
-        for col in &cols[1..cols.len()] {
-            let (mut name, mut lm) = gen_lm(&col);
-            col_uniq.append(&mut name);
-            lms.append(&mut lm);
-        }
-    }
-    let ncol = len;
-    let nrow = lms.len() as i64 / ncol;
+    let plist_alldate = get_params_list(&dates, holidays);
 
-    let lms = DMatrix::from_row_slice(nrow as usize, (ncol + 1) as usize, &lms);
-    return (lms, col_uniq);
+    Design::new(&plist_alldate)
 }
 
-// fn annual_lm(dates: &Vec<Date<Utc>>, first: Date<Utc>, last: Date<Utc>) {
-//     let lms = vec![0];
-//     monthdays_uni =
-// }
+///////////////////////////////////////////
+// Annual alignment
+//-----------------------------------------
+// 年度ごとの予定を年度内の相対的な位置で揃え，
+// 各日がそれらの位置にどれだけ近いかを返す．
+// 自分の年度の予定は使わない (leave-one-year-out) ので，
+// 学習期間の日でも予定そのものは手がかりにならない
+//
+// dates       : 評価する日
+// recurrence  : 予定発生履歴
+// fiscal_year : 年度の開始日
+// return      : 他の年度 1 つあたりのガウスカーネルの和
+///////////////////////////////////////////
+// Example
+//-----------------------------------------
+// recurrence : vec!['2019/6/10', '2020/6/8']
+// dates      : vec!['2021/6/9', '2021/6/20']
+// return     : vec![0.96, 0.00]
+///////////////////////////////////////////
+const ANNUAL_BANDWIDTH: f64 = 3.0;
+
+fn annual_lm(
+    dates: &Vec<Date<Utc>>,
+    recurrence: &Vec<Date<Utc>>,
+    fiscal_year: FiscalYear,
+) -> Vec<f64> {
+    let occurrences: Vec<(i32, f64)> = recurrence
+        .iter()
+        .map(|r| (fiscal_year.year_of(*r), fiscal_year.position(*r)))
+        .collect();
+
+    dates
+        .iter()
+        .map(|date| {
+            let year = fiscal_year.year_of(*date);
+            let position = fiscal_year.position(*date);
+            let mut years: Vec<i32> = occurrences
+                .iter()
+                .filter(|(y, _)| *y != year)
+                .map(|(y, _)| *y)
+                .collect();
+            years.sort();
+            years.dedup();
+            if years.is_empty() {
+                return 0.0;
+            }
+            let sum: f64 = occurrences
+                .iter()
+                .filter(|(y, _)| *y != year)
+                .map(|(_, p)| {
+                    // 年度をまたぐ位置の差は短い方をとる
+                    let d = (position - p).abs();
+                    let days = d.min(1.0 - d) * 365.25 / ANNUAL_BANDWIDTH;
+                    (-0.5 * days * days).exp()
+                })
+                .sum();
+            sum / years.len() as f64
+        })
+        .collect()
+}
 
 fn get_ts(recurrence: &Vec<Date<Utc>>, first: Date<Utc>, last: Date<Utc>) -> Vec<f64> {
     let len = (last - first).num_days();
-    let dates: Vec<Date<Utc>> = (0..=len).map(|x| first + Duration::days(x)).collect();
-    let mut ts = vec![0.0; dates.len()];
-    for (i, val) in dates.iter().enumerate() {
-        for r in recurrence {
-            if val == r {
-                ts[i] = 1.0;
-            }
+    let mut ts = vec![0.0; (len + 1) as usize];
+    for r in recurrence {
+        let i = (*r - first).num_days();
+        if 0 <= i && i <= len {
+            ts[i as usize] = 1.0;
         }
     }
     ts
 }
 
-fn lm_fit(ts: Vec<f64>, data: &DMatrix<f64>) -> (Vec<f64>, f64) {
-    // 重回帰
-    // let x = data.columns(1, data.ncols() - 1).into_owned();
-    let y = RowDVector::from_row_slice(&ts);
+// 係数と切片を求める回帰
+type Solver = fn(Vec<f64>, &Design) -> Result<(Vec<f64>, f64), HeronError>;
+
+// 重回帰
+//-----------------------------------------
+// one-hot 列は互いに従属しているので正規方程式を
+// 擬似逆行列で解く (予測値は最小二乗解によらない)
+fn lm_fit(ts: Vec<f64>, data: &Design) -> Result<(Vec<f64>, f64), HeronError> {
+    let p = data.ncols();
+    let g = data.gram(&vec![1.0; ts.len()]);
+    let xy = data.xtv(&ts);
+
+    let beta = g
+        .svd(true, true)
+        .solve(&xy, 1e-9)
+        .map_err(|e| HeronError::SingularRegression(e.to_string()))?;
+    check_finite(&beta)?;
+
+    let coefs = beta.rows(0, p).iter().cloned().collect();
+    let inter = beta[p];
+    return Ok((coefs, inter));
+}
+
+// 解に NaN や無限大が含まれていれば解けなかったとみなす
+fn check_finite(beta: &DVector<f64>) -> Result<(), HeronError> {
+    if beta.iter().all(|b| b.is_finite()) {
+        return Ok(());
+    }
+    Err(HeronError::SingularRegression(
+        "coefficients are not finite".to_string(),
+    ))
+}
+
+// ロジスティック回帰 (IRLS)
+//-----------------------------------------
+// one-hot 列は互いに従属しているので，切片以外に
+// L2 正則化をかけて解を一意にする．
+// Newton 法の更新で正則化つき対数尤度が下がるときは
+// 歩幅を半分にし，収束しなければ解けなかったとみなす
+const LOGIT_L2: f64 = 1.0;
+const LOGIT_MAX_ITER: usize = 100;
+const LOGIT_MAX_HALVING: usize = 30;
+const LOGIT_TOLERANCE: f64 = 1e-8;
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// log(1 + e^x) (大きな x でも溢れない)
+fn softplus(x: f64) -> f64 {
+    x.max(0.0) + (-x.abs()).exp().ln_1p()
+}
+
+// 正則化つき対数尤度
+fn logit_objective(data: &Design, y: &Vec<f64>, beta: &DVector<f64>) -> f64 {
+    let p = data.ncols();
+    let ll: f64 = data
+        .predict(beta)
+        .iter()
+        .zip(y.iter())
+        .map(|(eta, y)| y * eta - softplus(*eta))
+        .sum();
+    let penalty: f64 = beta.rows(0, p).iter().map(|b| b * b).sum();
+    ll - 0.5 * LOGIT_L2 * penalty
+}
 
-    let lr = LinearRegression::fit(
-        data,
-        &y,
-        LinearRegressionParameters {
-            solver: LinearRegressionSolverName::QR,
-        },
-    )
-    .unwrap();
+fn logit_fit(ts: Vec<f64>, data: &Design) -> Result<(Vec<f64>, f64), HeronError> {
+    let p = data.ncols();
+    let y = ts;
+
+    // 切片は最後の要素
+    let mut beta = DVector::<f64>::zeros(p + 1);
+    let rate = (y.iter().sum::<f64>() / y.len() as f64)
+        .max(1e-6)
+        .min(1.0 - 1e-6);
+    beta[p] = (rate / (1.0 - rate)).ln();
+    let mut objective = logit_objective(data, &y, &beta);
+
+    for _ in 0..LOGIT_MAX_ITER {
+        let prob: Vec<f64> = data.predict(&beta).into_iter().map(sigmoid).collect();
+        let weights = prob.iter().map(|q| q * (1.0 - q)).collect();
+        let residuals = y.iter().zip(prob.iter()).map(|(y, q)| y - q).collect();
+
+        let mut hessian = data.gram(&weights);
+        let mut grad = data.xtv(&residuals);
+        for j in 0..p {
+            hessian[(j, j)] += LOGIT_L2;
+            grad[j] -= LOGIT_L2 * beta[j];
+        }
 
-    let coefs = lr.coefficients().as_slice().to_vec();
-    let inter = lr.intercept();
-    return (coefs, inter);
+        let mut step = match hessian.cholesky() {
+            Some(c) => c.solve(&grad),
+            None => {
+                return Err(HeronError::SingularRegression(
+                    "logistic Hessian is not positive definite".to_string(),
+                ))
+            }
+        };
+        // 最適解の近くでは丸め誤差の範囲で尤度が下がっても受け入れる
+        let margin = LOGIT_TOLERANCE * (1.0 + objective.abs());
+        let mut halving = 0;
+        loop {
+            let next = &beta + &step;
+            let next_objective = logit_objective(data, &y, &next);
+            if next_objective >= objective - margin {
+                let converged = step.amax() < LOGIT_TOLERANCE
+                    || (halving == 0 && (next_objective - objective).abs() < margin);
+                beta = next;
+                objective = next_objective;
+                check_finite(&beta)?;
+                if converged {
+                    let coefs = beta.rows(0, p).iter().cloned().collect();
+                    let inter = beta[p];
+                    return Ok((coefs, inter));
+                }
+                break;
+            }
+            halving += 1;
+            if halving > LOGIT_MAX_HALVING {
+                return Err(HeronError::SingularRegression(
+                    "logistic regression did not converge".to_string(),
+                ));
+            }
+            step /= 2.0;
+        }
+    }
+    Err(HeronError::SingularRegression(
+        "logistic regression did not converge".to_string(),
+    ))
 }
 
-fn get_w(ts: Vec<f64>, lm: &mut DMatrix<f64>) -> (Vec<f64>, f64) {
-    let dx = lm.transpose();
+fn get_w(ts: Vec<f64>, lm: &Design, fit: Solver) -> Result<(Vec<f64>, f64), HeronError> {
+    let (coefs, inter) = fit(ts, lm)?;
+    return Ok((coefs, inter));
+}
 
-    // 重回帰
-    let (coefs, inter) = lm_fit(ts, &dx);
-    return (coefs, inter);
+fn get_f(candidates_rows: &Vec<Vec<(usize, f64)>>, coefs: &Vec<f64>, inter: f64) -> Vec<f64> {
+    candidates_rows
+        .iter()
+        .map(|row| row.iter().map(|(j, x)| coefs[*j] * x).sum::<f64>() + inter)
+        .collect()
 }
 
-fn get_f(
-    candidates_plist: &DataFrame,
+// 暦の特徴量に当てはめたモデル
+struct Model {
+    holidays: Arc<dyn HolidayProvider>,
+    max_period: Option<usize>,
+    fiscal_year: FiscalYear,
+    range_candidate: Vec<i64>,
+    periods: Vec<Period>,
     coefs: Vec<f64>,
     inter: f64,
-    colname: Vec<String>,
-) -> DVector<f64> {
-    let mut m = DMatrix::<f64>::zeros(candidates_plist.height(), colname.len());
+    lm: Design,
+}
+
+impl Model {
+    fn new(options: &Options) -> Model {
+        Model {
+            holidays: options.holidays.clone(),
+            max_period: options.max_period,
+            fiscal_year: options.fiscal_year,
+            range_candidate: vec![],
+            periods: vec![],
+            coefs: vec![],
+            inter: 0.0,
+            lm: Design::new(&vec![]),
+        }
+    }
 
-    let cols = candidates_plist.get_columns();
+    fn fit(
+        &mut self,
+        events: &Vec<Date<Utc>>,
+        range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+        solver: Solver,
+    ) -> Result<(), HeronError> {
+        // recurrence: 予定発生履歴
+        let first = range_recurrence[0];
+        let last = range_recurrence[1];
+        let recurrence = events;
+        let periods = periodicity::detect_periods(&recurrence, &range_recurrence, self.max_period);
+
+        let mut lm = get_lm_all(first, last, self.holidays.as_ref());
+        let len = (last - first).num_days();
+        let dates: Vec<Date<Utc>> = (0..=len).map(|x| first + Duration::days(x)).collect();
+        let annu_lm = annual_lm(&dates, &recurrence, self.fiscal_year);
+        lm.push_column(Feature::AnnualAlignment, &annu_lm);
+
+        let ts = get_ts(&recurrence, first, last);
+        let (coefs, inter) = get_w(ts, &lm, solver)?;
+
+        self.range_candidate = range_candidate.clone();
+        self.periods = periods;
+        self.coefs = coefs;
+        self.inter = inter;
+        self.lm = lm;
+        Ok(())
+    }
 
-    for col in cols.iter() {
-        for i in 0..m.nrows() {
-            for (j, val) in colname.iter().enumerate() {
-                if col.get(i).to_string() == *val {
-                    m[(i, j)] = 1.0;
+    // 候補日とその線形予測子
+    fn candidates(&self, events: &Vec<Date<Utc>>) -> Vec<Candidate> {
+        // 次の予定の候補日
+        let candidates = get_candidates_all(events, &self.range_candidate, &self.periods);
+        let dates = candidates.iter().map(|c| c.0).collect();
+        let candidates_plist = get_params_list(&dates, self.holidays.as_ref());
+        let annu_lm = annual_lm(&dates, events, self.fiscal_year);
+        let candidates_rows: Vec<Vec<(usize, f64)>> = candidates_plist
+            .iter()
+            .zip(annu_lm.iter())
+            .map(|(params, annual)| {
+                let mut row = self.lm.encode(params);
+                if let Some(col) = self.lm.index.get(&Feature::AnnualAlignment) {
+                    row.push((*col, *annual));
                 }
-            }
+                row
+            })
+            .collect();
+
+        let f = get_f(&candidates_rows, &self.coefs, self.inter);
+
+        candidates
+            .into_iter()
+            .zip(f.into_iter())
+            .zip(candidates_plist.into_iter())
+            .zip(candidates_rows.iter())
+            .map(|((((date, period, pivot), score), features), row)| {
+                let contributions = row
+                    .iter()
+                    .map(|(j, x)| Contribution {
+                        feature: self.lm.cols[*j],
+                        weight: self.coefs[*j] * x,
+                    })
+                    .collect();
+                let mut candidate = Candidate::new(date, score, features);
+                candidate.period = Some(period);
+                candidate.pivot = Some(pivot);
+                candidate.contributions = contributions;
+                candidate
+            })
+            .collect()
+    }
+
+    fn forecast(&self, candidates: Vec<Candidate>) -> Forecast {
+        let mut forecast = Forecast::new(candidates);
+        forecast.periods = self.periods.clone();
+        forecast.intercept = Some(self.inter);
+        forecast
+    }
+}
+
+///////////////////////////////////////////
+// Linear regression on calendar features
+//-----------------------------------------
+// 曜日・週・月・日・第 n 曜日・最終曜日・月末までの営業日数の
+// one-hot 列と年度内の位置の揃い具合 (annual_lm) に重回帰し，
+// 候補日のうち最もスコアの高い日を選ぶ
+///////////////////////////////////////////
+pub struct LinearForecaster {
+    model: Model,
+}
+
+impl LinearForecaster {
+    pub fn new(options: &Options) -> LinearForecaster {
+        LinearForecaster {
+            model: Model::new(options),
         }
     }
+}
 
-    let coefs = DVector::from_vec(coefs);
-    let f = (m * coefs).add_scalar(inter);
+impl Forecaster for LinearForecaster {
+    fn fit(
+        &mut self,
+        events: &Vec<Date<Utc>>,
+        range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+    ) -> Result<(), HeronError> {
+        self.model
+            .fit(events, range_recurrence, range_candidate, lm_fit)
+    }
+
+    fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast {
+        let candidates = self.model.candidates(events);
 
-    f
+        self.model.forecast(candidates)
+    }
 }
 
-fn max_index(array: DVector<f64>) -> usize {
-    let mut index: usize = 0;
-    for i in 0..array.len() {
-        if array[index] < array[i] {
-            index = i;
+///////////////////////////////////////////
+// Logistic regression on calendar features
+//-----------------------------------------
+// 同じ one-hot 列にロジスティック回帰し，
+// 各候補日が発生日である確率を求める
+///////////////////////////////////////////
+pub struct LogisticForecaster {
+    model: Model,
+}
+
+impl LogisticForecaster {
+    pub fn new(options: &Options) -> LogisticForecaster {
+        LogisticForecaster {
+            model: Model::new(options),
         }
     }
-    index
 }
 
-pub fn forecast(
-    range_recurrence: &Vec<Date<Utc>>,
-    range_candidate: &Vec<i64>,
-    events: &Vec<Date<Utc>>,
-) -> Date<Utc> {
-    // recurrence: 予定発生履歴
-    let first = range_recurrence[0];
-    let last = range_recurrence[1];
-    let recurrence = events;
-    // 次の予定の候補日
-    let mut period = get_big_wave_cycle(&recurrence, &range_recurrence);
-    if period == 0 {
-        period = 365;
+impl Forecaster for LogisticForecaster {
+    fn fit(
+        &mut self,
+        events: &Vec<Date<Utc>>,
+        range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+    ) -> Result<(), HeronError> {
+        self.model
+            .fit(events, range_recurrence, range_candidate, logit_fit)
     }
 
-    let candidates = get_candidates(&recurrence, &range_candidate, period);
-    let candidates_plist = get_params_list(&candidates);
+    // スコアとして確率を用いる
+    fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast {
+        let mut candidates = self.model.candidates(events);
+        for candidate in candidates.iter_mut() {
+            let p = sigmoid(candidate.score);
+            candidate.probability = Some(p);
+            candidate.score = p;
+        }
 
-    let (mut lm, cols) = get_lm_all(first, last);
-    // let annu_lm = annual_lm(&recurrence, first, last);
-    // println!("lm: {:?}", lm);
+        self.model.forecast(candidates)
+    }
+}
 
-    let ts = get_ts(&recurrence, first, last);
-    let (coefs, inter) = get_w(ts, &mut lm);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::feature::DayOfWeek;
+    use crate::forecast::holiday::{JapaneseHolidays, NoHolidays};
+
+    // 以前の密な one-hot 行列 (切片は最後の列)
+    fn dense(data: &Design) -> DMatrix<f64> {
+        let p = data.ncols();
+        let mut x = DMatrix::<f64>::zeros(data.rows.len(), p + 1);
+        for (i, row) in data.rows.iter().enumerate() {
+            for (j, v) in row.iter() {
+                x[(i, *j)] = *v;
+            }
+            x[(i, p)] = 1.0;
+        }
+        x
+    }
+
+    fn split(beta: DVector<f64>, p: usize) -> (Vec<f64>, f64) {
+        (beta.rows(0, p).iter().cloned().collect(), beta[p])
+    }
+
+    // 密な行列の最小二乗解 (最小ノルム)
+    fn dense_lm_fit(ts: Vec<f64>, data: &Design) -> Result<(Vec<f64>, f64), HeronError> {
+        let y = DVector::from_vec(ts);
+        let beta = dense(data).svd(true, true).solve(&y, 1e-9).unwrap();
+        Ok(split(beta, data.ncols()))
+    }
+
+    // 密な行列での IRLS
+    fn dense_logit_fit(ts: Vec<f64>, data: &Design) -> Result<(Vec<f64>, f64), HeronError> {
+        let p = data.ncols();
+        let x = dense(data);
+        let y = DVector::from_vec(ts);
+        let mut beta = DVector::<f64>::zeros(p + 1);
+        let rate = y.mean().max(1e-6).min(1.0 - 1e-6);
+        beta[p] = (rate / (1.0 - rate)).ln();
+
+        for _ in 0..LOGIT_MAX_ITER {
+            let prob = (&x * &beta).map(sigmoid);
+            let mut xw = x.clone();
+            for i in 0..x.nrows() {
+                let w = prob[i] * (1.0 - prob[i]);
+                for j in 0..=p {
+                    xw[(i, j)] *= w;
+                }
+            }
+            let mut hessian = x.transpose() * xw;
+            let mut grad = x.transpose() * (&y - &prob);
+            for j in 0..p {
+                hessian[(j, j)] += LOGIT_L2;
+                grad[j] -= LOGIT_L2 * beta[j];
+            }
+            let mut step = hessian.cholesky().unwrap().solve(&grad);
+            if step.amax() < LOGIT_TOLERANCE {
+                beta += &step;
+                return Ok(split(beta, p));
+            }
+            // 正則化つき対数尤度が下がらない歩幅まで半分にする
+            let objective = |b: &DVector<f64>| {
+                let eta = &x * b;
+                let ll: f64 = (0..eta.len())
+                    .map(|i| y[i] * eta[i] - softplus(eta[i]))
+                    .sum();
+                ll - 0.5 * LOGIT_L2 * b.rows(0, p).norm_squared()
+            };
+            while objective(&(&beta + &step)) < objective(&beta) {
+                step /= 2.0;
+            }
+            beta += &step;
+        }
+        panic!("dense IRLS did not converge");
+    }
+
+    // 第 2 水曜日 (2017 年度から 2020 年度)
+    fn seminar() -> Vec<Date<Utc>> {
+        (0..48)
+            .map(|i| {
+                let first = Utc.ymd(2017 + (3 + i) / 12, ((3 + i) % 12 + 1) as u32, 1);
+                let offset = (7 + 2 - first.weekday().num_days_from_monday() as i32) % 7;
+                first + Duration::days(offset as i64 + 7)
+            })
+            .collect()
+    }
+
+    fn fitted(solver: Solver) -> Model {
+        let options = Options::new(Arc::new(NoHolidays));
+        let events = seminar();
+        let range = options.fiscal_year.sampling_range(&events, None);
+        let mut model = Model::new(&options);
+        model
+            .fit(&events, &range, &(-3..4).collect(), solver)
+            .unwrap();
+        model
+    }
+
+    fn assert_same_forecast(sparse: Solver, dense: Solver) {
+        let events = seminar();
+        let (a, b) = (fitted(sparse), fitted(dense));
+
+        let fa = a.lm.predict(&DVector::from_iterator(
+            a.coefs.len() + 1,
+            a.coefs.iter().cloned().chain(Some(a.inter)),
+        ));
+        let fb = b.lm.predict(&DVector::from_iterator(
+            b.coefs.len() + 1,
+            b.coefs.iter().cloned().chain(Some(b.inter)),
+        ));
+        for (x, y) in fa.iter().zip(fb.iter()) {
+            assert!((x - y).abs() < 1e-6, "{} != {}", x, y);
+        }
 
-    let f = get_f(&candidates_plist, coefs, inter, cols);
+        let ca = a.candidates(&events);
+        let cb = b.candidates(&events);
+        assert_eq!(ca.len(), cb.len());
+        for (x, y) in ca.iter().zip(cb.iter()) {
+            assert_eq!(x.date, y.date);
+            assert!((x.score - y.score).abs() < 1e-6);
+        }
+        assert_eq!(
+            a.forecast(ca).best().map(|c| c.date),
+            b.forecast(cb).best().map(|c| c.date)
+        );
+    }
+
+    #[test]
+    fn nth_and_last_weekday() {
+        let nth = |date: Date<Utc>| match nth_weekday(&date) {
+            Feature::NthWeekday { nth, weekday } => (nth, weekday),
+            f => panic!("{:?}", f),
+        };
+        assert_eq!(nth(Utc.ymd(2021, 4, 1)), (1, DayOfWeek::Thu));
+        assert_eq!(nth(Utc.ymd(2021, 4, 14)), (2, DayOfWeek::Wed));
+        // 第 5 木曜日は最終木曜日でもある
+        assert_eq!(nth(Utc.ymd(2021, 4, 29)), (5, DayOfWeek::Thu));
+        assert_eq!(
+            last_weekday(&Utc.ymd(2021, 4, 29)),
+            Feature::LastWeekday(DayOfWeek::Thu)
+        );
+
+        // 最終金曜日
+        assert_eq!(
+            last_weekday(&Utc.ymd(2021, 4, 30)),
+            Feature::LastWeekday(DayOfWeek::Fri)
+        );
+        assert_eq!(last_weekday(&Utc.ymd(2021, 4, 23)), Feature::NotLastWeekday);
+        // 2021 年 2 月は 22 日以降が最終週
+        assert_eq!(
+            last_weekday(&Utc.ymd(2021, 2, 22)),
+            Feature::LastWeekday(DayOfWeek::Mon)
+        );
+        assert_eq!(last_weekday(&Utc.ymd(2021, 2, 21)), Feature::NotLastWeekday);
+    }
 
-    let index = max_index(f);
+    #[test]
+    fn business_days_skip_holidays_and_weekends() {
+        let jp = JapaneseHolidays::new();
+        let days =
+            |date: Date<Utc>, holidays: &dyn HolidayProvider| match business_days_to_month_end(
+                &date, holidays,
+            ) {
+                Feature::BusinessDaysToMonthEnd(n) => n,
+                f => panic!("{:?}", f),
+            };
+        // 4/29 (木) は昭和の日なので 4/30 (金) だけ
+        assert_eq!(days(Utc.ymd(2021, 4, 28), &jp), 1);
+        assert_eq!(days(Utc.ymd(2021, 4, 28), &NoHolidays), 2);
+        assert_eq!(days(Utc.ymd(2021, 4, 30), &jp), 0);
+        // 7/31 は土曜日
+        assert_eq!(days(Utc.ymd(2021, 7, 30), &jp), 0);
+        // 11/23 (火) は勤労感謝の日
+        assert_eq!(days(Utc.ymd(2021, 11, 22), &jp), 5);
+        assert_eq!(days(Utc.ymd(2021, 11, 22), &NoHolidays), 6);
+    }
 
-    let forecasted = candidates[index];
+    #[test]
+    fn annual_lm_example() {
+        let recurrence = vec![Utc.ymd(2019, 6, 10), Utc.ymd(2020, 6, 8)];
+        let dates = vec![Utc.ymd(2021, 6, 9), Utc.ymd(2021, 6, 20)];
+        let annual = annual_lm(&dates, &recurrence, FiscalYear::default());
+        let rounded: Vec<f64> = annual.iter().map(|a| (a * 100.0).round() / 100.0).collect();
+        assert_eq!(rounded, vec![0.96, 0.00]);
+    }
+
+    #[test]
+    fn annual_lm_excludes_own_fiscal_year() {
+        let recurrence = vec![Utc.ymd(2019, 6, 10), Utc.ymd(2020, 6, 8)];
+        // 2020 年度の予定そのものは手がかりにならず，2019 年度の予定だけを見る
+        let own = annual_lm(
+            &vec![Utc.ymd(2020, 6, 8)],
+            &recurrence,
+            FiscalYear::default(),
+        );
+        let other = annual_lm(
+            &vec![Utc.ymd(2021, 6, 8)],
+            &vec![Utc.ymd(2019, 6, 10)],
+            FiscalYear::default(),
+        );
+        assert!((own[0] - other[0]).abs() < 1e-12);
+        assert!(own[0] < 0.9);
+        // 年度の区切りは年度の開始日で決まる (2020/3/31 は 2019 年度)
+        let march = annual_lm(
+            &vec![Utc.ymd(2020, 3, 31)],
+            &vec![Utc.ymd(2019, 4, 1)],
+            FiscalYear::default(),
+        );
+        assert_eq!(march, vec![0.0]);
+        // 他の年度がなければ 0
+        let alone = annual_lm(
+            &vec![Utc.ymd(2019, 6, 10)],
+            &vec![Utc.ymd(2019, 6, 10)],
+            FiscalYear::default(),
+        );
+        assert_eq!(alone, vec![0.0]);
+    }
+
+    #[test]
+    fn seminar_is_second_wednesday() {
+        let events = seminar();
+        assert_eq!(events[0], Utc.ymd(2017, 4, 12));
+        assert_eq!(events[47], Utc.ymd(2021, 3, 10));
+        assert!(events.iter().all(|d| d.weekday() == Weekday::Wed));
+    }
 
-    forecasted
+    #[test]
+    fn sparse_lm_matches_dense() {
+        assert_same_forecast(lm_fit, dense_lm_fit);
+    }
+
+    #[test]
+    fn logistic_forecast_is_second_wednesday() {
+        let events = seminar();
+        let model = fitted(logit_fit);
+        let mut forecaster = LogisticForecaster::new(&Options::new(Arc::new(NoHolidays)));
+        forecaster.model = model;
+        let forecast = forecaster.rank(&events);
+        let best = forecast.best().unwrap();
+
+        assert_eq!(best.date, Utc.ymd(2021, 4, 14));
+        assert_eq!(
+            nth_weekday(&best.date),
+            Feature::NthWeekday {
+                nth: 2,
+                weekday: DayOfWeek::Wed
+            }
+        );
+        let p = best.probability.unwrap();
+        assert!(0.5 < p && p < 1.0, "p = {}", p);
+    }
+
+    #[test]
+    fn logistic_converges_on_short_history() {
+        let options = Options::new(Arc::new(NoHolidays));
+        for n in 5..=12 {
+            let events = seminar()[..n].to_vec();
+            let range = options.fiscal_year.sampling_range(&events, None);
+            let mut model = Model::new(&options);
+            let fit = model.fit(&events, &range, &(-3..4).collect(), logit_fit);
+            assert!(fit.is_ok(), "{} events: {:?}", n, fit.err());
+        }
+    }
+
+    #[test]
+    fn sparse_logit_matches_dense() {
+        let (a, b) = (fitted(logit_fit), fitted(dense_logit_fit));
+        for (x, y) in a.coefs.iter().zip(b.coefs.iter()) {
+            assert!((x - y).abs() < 1e-6, "{} != {}", x, y);
+        }
+        assert_same_forecast(logit_fit, dense_logit_fit);
+    }
 }
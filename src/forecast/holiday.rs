@@ -0,0 +1,315 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use jpholiday::jpholiday::JPHoliday;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+///////////////////////////////////////////
+// Holiday provider
+//-----------------------------------------
+// 祝日・休業日の判定
+///////////////////////////////////////////
+pub trait HolidayProvider: Send + Sync {
+    fn is_holiday(&self, date: &NaiveDate) -> bool;
+}
+
+// 日本の祝日
+//-----------------------------------------
+// JPHoliday は Send + Sync でなく，1 日ずつの判定も重いので，
+// 初めて問い合わせのあった年の祝日をまとめて求めて持っておく
+pub struct JapaneseHolidays {
+    years: Mutex<HashMap<i32, HashSet<NaiveDate>>>,
+}
+
+impl JapaneseHolidays {
+    pub fn new() -> JapaneseHolidays {
+        JapaneseHolidays {
+            years: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl HolidayProvider for JapaneseHolidays {
+    fn is_holiday(&self, date: &NaiveDate) -> bool {
+        let mut years = self.years.lock().unwrap_or_else(|e| e.into_inner());
+        years
+            .entry(date.year())
+            .or_insert_with(|| {
+                JPHoliday::new()
+                    .year_holidays(date.year())
+                    .into_iter()
+                    .map(|(d, _)| d)
+                    .collect()
+            })
+            .contains(date)
+    }
+}
+
+// 祝日なし
+pub struct NoHolidays;
+
+impl HolidayProvider for NoHolidays {
+    fn is_holiday(&self, _date: &NaiveDate) -> bool {
+        false
+    }
+}
+
+///////////////////////////////////////////
+// Holidays from a local file
+//-----------------------------------------
+// CSV : 1 列目が YYYY-MM-DD (# 以降はコメント)
+//   2021-12-29,年末休業
+// ICS : VEVENT の DTSTART (終日予定は DTEND の前日まで)
+//       折り返された行 (空白かタブで始まる行) は前の行につなげる
+///////////////////////////////////////////
+pub struct FileHolidays {
+    dates: HashSet<NaiveDate>,
+}
+
+impl FileHolidays {
+    pub fn load(path: &str) -> Result<FileHolidays, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let dates = if path.to_lowercase().ends_with(".ics") {
+            parse_ics(&text)
+        } else {
+            parse_csv(&text)
+        };
+        dates
+            .map(|dates| FileHolidays { dates })
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+impl HolidayProvider for FileHolidays {
+    fn is_holiday(&self, date: &NaiveDate) -> bool {
+        self.dates.contains(date)
+    }
+}
+
+fn parse_csv(text: &str) -> Result<HashSet<NaiveDate>, String> {
+    let mut dates = HashSet::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let field = line.split(',').next().unwrap_or("").trim();
+        let date = NaiveDate::parse_from_str(field, "%Y-%m-%d")
+            .map_err(|_| format!("line {}: invalid date {:?}", i + 1, field))?;
+        dates.insert(date);
+    }
+    Ok(dates)
+}
+
+// "DTSTART;VALUE=DATE:20211229" の日付部分
+fn ics_date(line: &str) -> Option<NaiveDate> {
+    let value = line.rsplit(':').next()?;
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+// RFC 5545 の折り返しを戻す (行番号は論理行の先頭の行)
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        match lines.last_mut() {
+            Some((_, last)) if line.starts_with(' ') || line.starts_with('\t') => {
+                last.push_str(&line[1..])
+            }
+            _ => lines.push((i, line.to_string())),
+        }
+    }
+    lines
+}
+
+fn parse_ics(text: &str) -> Result<HashSet<NaiveDate>, String> {
+    let mut dates = HashSet::new();
+    let mut start: Option<NaiveDate> = None;
+    let mut end: Option<NaiveDate> = None;
+    let mut all_day = false;
+    for (i, line) in unfold(text) {
+        let line = line.trim();
+        if line.starts_with("BEGIN:VEVENT") {
+            start = None;
+            end = None;
+            all_day = false;
+        } else if line.starts_with("DTSTART") {
+            start = Some(ics_date(line).ok_or_else(|| format!("line {}: invalid DTSTART", i + 1))?);
+            all_day = line.contains("VALUE=DATE:");
+        } else if line.starts_with("DTEND") {
+            end = ics_date(line);
+        } else if line.starts_with("END:VEVENT") {
+            if let Some(s) = start {
+                dates.insert(s);
+                // 終日予定の DTEND は翌日を指す
+                if let (true, Some(e)) = (all_day, end) {
+                    let mut d = s + Duration::days(1);
+                    while d < e {
+                        dates.insert(d);
+                        d = d + Duration::days(1);
+                    }
+                }
+            }
+        }
+    }
+    Ok(dates)
+}
+
+// いずれかが祝日なら祝日
+pub struct CompositeHolidays {
+    providers: Vec<Box<dyn HolidayProvider>>,
+}
+
+impl HolidayProvider for CompositeHolidays {
+    fn is_holiday(&self, date: &NaiveDate) -> bool {
+        self.providers.iter().any(|p| p.is_holiday(date))
+    }
+}
+
+///////////////////////////////////////////
+// Build a provider from the CLI
+//-----------------------------------------
+// spec : カンマ区切りで組み合わせる
+//   jp        : 日本の祝日
+//   none      : 祝日なし
+//   file:PATH : CSV / ICS ファイル
+// Example: "jp,file:closures.csv"
+///////////////////////////////////////////
+pub fn from_spec(spec: &str) -> Result<Arc<dyn HolidayProvider>, String> {
+    let mut providers: Vec<Box<dyn HolidayProvider>> = vec![];
+    for item in split_spec(spec).iter().map(|s| s.as_str()) {
+        if item == "jp" {
+            providers.push(Box::new(JapaneseHolidays::new()));
+        } else if item == "none" {
+            providers.push(Box::new(NoHolidays));
+        } else if item.starts_with("file:") {
+            providers.push(Box::new(FileHolidays::load(&item["file:".len()..])?));
+        } else {
+            return Err(format!("No matching holiday provider: {}", item));
+        }
+    }
+    Ok(Arc::new(CompositeHolidays { providers }))
+}
+
+// PATH はカンマを含みうるので，jp, none, file: で始まらない部分は直前の PATH の続きとする
+fn split_spec(spec: &str) -> Vec<String> {
+    let mut items: Vec<String> = vec![];
+    for part in spec.split(',') {
+        let item = part.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let known = item == "jp" || item == "none" || item.starts_with("file:");
+        match items.last_mut() {
+            Some(last) if !known && last.starts_with("file:") => {
+                last.push(',');
+                last.push_str(part);
+            }
+            _ => items.push(item.to_string()),
+        }
+    }
+    items.iter().map(|s| s.trim_end().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    fn set(dates: Vec<NaiveDate>) -> HashSet<NaiveDate> {
+        dates.into_iter().collect()
+    }
+
+    #[test]
+    fn japanese_holidays_include_substitute_days() {
+        let holidays = JapaneseHolidays::new();
+        assert!(holidays.is_holiday(&ymd(2017, 1, 1)));
+        // 振替休日
+        assert!(holidays.is_holiday(&ymd(2017, 1, 2)));
+        assert!(!holidays.is_holiday(&ymd(2017, 1, 3)));
+        assert!(holidays.is_holiday(&ymd(2017, 5, 5)));
+        assert!(!holidays.is_holiday(&ymd(2018, 5, 7)));
+    }
+
+    #[test]
+    fn csv_reads_first_column() {
+        let text = "# 休業日\n\
+                    2021-12-29,年末休業\n\
+                    \n\
+                    2022-01-04 # 仕事始め\n";
+        assert_eq!(
+            parse_csv(text).unwrap(),
+            set(vec![ymd(2021, 12, 29), ymd(2022, 1, 4)])
+        );
+    }
+
+    #[test]
+    fn csv_reports_invalid_line() {
+        let err = parse_csv("2021-12-29\n2021/12/30\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn ics_expands_all_day_events() {
+        let text = "BEGIN:VCALENDAR\n\
+                    BEGIN:VEVENT\n\
+                    DTSTART;VALUE=DATE:20211229\n\
+                    DTEND;VALUE=DATE:20220101\n\
+                    END:VEVENT\n\
+                    BEGIN:VEVENT\n\
+                    DTSTART:20220104T090000Z\n\
+                    DTEND:20220106T170000Z\n\
+                    END:VEVENT\n\
+                    END:VCALENDAR\n";
+        // 時刻つきの予定は開始日だけ
+        assert_eq!(
+            parse_ics(text).unwrap(),
+            set(vec![
+                ymd(2021, 12, 29),
+                ymd(2021, 12, 30),
+                ymd(2021, 12, 31),
+                ymd(2022, 1, 4),
+            ])
+        );
+    }
+
+    #[test]
+    fn ics_reports_invalid_dtstart() {
+        let err = parse_ics("BEGIN:VEVENT\nDTSTART:2021\nEND:VEVENT\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn ics_unfolds_continuation_lines() {
+        let text = "BEGIN:VCALENDAR\r\n\
+                    BEGIN:VEVENT\r\n\
+                    DTSTART;VALUE=DATE:2021\r\n \
+                    1229\r\n\
+                    DTEND;VALUE\r\n\t=DATE:20211231\r\n\
+                    SUMMARY:年末\r\n  休業\r\n\
+                    END:VEVENT\r\n\
+                    END:VCALENDAR\r\n";
+        assert_eq!(
+            parse_ics(text).unwrap(),
+            set(vec![ymd(2021, 12, 29), ymd(2021, 12, 30)])
+        );
+    }
+
+    #[test]
+    fn spec_keeps_commas_in_file_path() {
+        assert_eq!(
+            split_spec("none, file:a,b.csv ,file:c.ics,jp"),
+            vec!["none", "file:a,b.csv", "file:c.ics", "jp"]
+        );
+
+        let path = env::temp_dir().join(format!("heron,holidays-{}.csv", std::process::id()));
+        fs::write(&path, "2021-12-29\n").unwrap();
+        let provider = from_spec(&format!("none,file:{}", path.display())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(provider.is_holiday(&ymd(2021, 12, 29)));
+        assert!(!provider.is_holiday(&ymd(2021, 12, 30)));
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::{Date, Utc};
+
+use super::fiscal_year::FiscalYear;
+
+///////////////////////////////////////////
+// Forecast horizon
+//-----------------------------------------
+// Next          : 次の 1 件 (候補日をすべて残す)
+// Count(n)      : 次の n 件
+// Until(d)      : d までの全て
+// Between(a, b) : a から b までの全て
+// Year(y)       : y 年度内の全て
+//
+// Example (履歴の最後が 2021-04-14，毎月第 2 水曜日)
+//   Count(3)                         : 05-12, 06-09, 07-14
+//   Until(2021-06-30)                : 05-12, 06-09
+//   Between(2021-06-01, 2021-07-31)  : 06-09, 07-14
+///////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Horizon {
+    Next,
+    Count(usize),
+    Until(Date<Utc>),
+    Between(Date<Utc>, Date<Utc>),
+    Year(i32),
+}
+
+impl Horizon {
+    // 日付で区切る範囲 (from, until)．from が None なら直前の予定から
+    pub fn window(&self, fiscal_year: FiscalYear) -> Option<(Option<Date<Utc>>, Date<Utc>)> {
+        match *self {
+            Horizon::Next | Horizon::Count(_) => None,
+            Horizon::Until(until) => Some((None, until)),
+            Horizon::Between(from, until) => Some((Some(from), until)),
+            Horizon::Year(year) => Some((
+                Some(fiscal_year.start(year)),
+                fiscal_year.start(year + 1).pred(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use chrono::{Date, Utc};
+
+use super::result::Forecast;
+
+// 予測した予定であることを示すプロパティ
+pub const FORECAST_PROPERTY: &str = "X-HERON-FORECAST";
+
+const PRODID: &str = "-//nomlab//heron//EN";
+// 1 行の長さの上限 (改行を除くオクテット数)
+const MAX_LINE: usize = 75;
+
+///////////////////////////////////////////
+// Stable UID
+//-----------------------------------------
+// リカーレンス名と日付の FNV-1a (64 bit) から作る．
+// 同じ予測を何度書き出しても同じ UID になるので，
+// カレンダーに読み込み直すと上書きされる
+///////////////////////////////////////////
+// Example
+//-----------------------------------------
+// recurrence : "seminar"
+// date       : '2021/6/8'
+// return     : "9da94f793f9e345d-20210608@heron"
+///////////////////////////////////////////
+pub fn uid(recurrence: &str, date: Date<Utc>) -> String {
+    let ymd = date.format("%Y%m%d").to_string();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in recurrence.bytes().chain(Some(0)).chain(ymd.bytes()) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}-{}@heron", hash, ymd)
+}
+
+// TEXT 値のエスケープ
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// 長い行は 75 オクテットごとに折り返す (続きの行は空白で始める)
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded
+}
+
+///////////////////////////////////////////
+// Export forecasts as iCalendar
+//-----------------------------------------
+// forecasts : (リカーレンス名, 予測) の列
+// return    : 各予測の最有力の日を終日の VEVENT にした
+//             VCALENDAR
+//
+// SUMMARY はリカーレンス名，STATUS は TENTATIVE とし，
+// X-HERON-FORECAST:TRUE とスコア (X-HERON-SCORE) を付ける
+///////////////////////////////////////////
+pub fn to_ics(forecasts: &Vec<(String, Forecast)>) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODID),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for (recurrence, forecast) in forecasts {
+        let best = match forecast.best() {
+            Some(b) => b,
+            None => continue,
+        };
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", uid(recurrence, best.date)));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", best.date.format("%Y%m%d")));
+        lines.push(format!(
+            "DTEND;VALUE=DATE:{}",
+            best.date.succ().format("%Y%m%d")
+        ));
+        lines.push(format!("SUMMARY:{}", escape(recurrence)));
+        lines.push("STATUS:TENTATIVE".to_string());
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push(format!("{}:TRUE", FORECAST_PROPERTY));
+        lines.push(format!("X-HERON-SCORE:{:.4}", best.score));
+        if let Some(p) = best.probability {
+            lines.push(format!("X-HERON-PROBABILITY:{:.4}", p));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|l| fold(l))
+        .collect::<Vec<String>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::result::Candidate;
+    use chrono::TimeZone;
+
+    #[test]
+    fn uid_is_stable() {
+        let date = Utc.ymd(2021, 6, 8);
+        assert_eq!(uid("seminar", date), "9da94f793f9e345d-20210608@heron");
+        assert_eq!(uid("seminar", date), uid("seminar", date));
+        assert_ne!(uid("seminar", date), uid("seminar", Utc.ymd(2021, 6, 9)));
+        assert_ne!(uid("seminar", date), uid("meeting", date));
+    }
+
+    #[test]
+    fn fold_limits_lines_to_75_octets() {
+        let line = format!("SUMMARY:{}", "研究室ミーティング".repeat(10));
+        let folded = fold(&line);
+        for l in folded.split("\r\n") {
+            assert!(l.len() <= MAX_LINE, "{} octets", l.len());
+        }
+        // 続きの行の先頭の空白を除けば元に戻る
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert_eq!(fold("SUMMARY:seminar"), "SUMMARY:seminar");
+    }
+
+    #[test]
+    fn to_ics_writes_folded_events() {
+        let name = "seminar".repeat(12);
+        let date = Utc.ymd(2021, 6, 8);
+        let forecasts = vec![
+            (
+                name.clone(),
+                Forecast::new(vec![Candidate::new(date, 0.5, vec![])]),
+            ),
+            // 候補日のない予測は書き出さない
+            ("meeting".to_string(), Forecast::new(vec![])),
+        ];
+        let ics = to_ics(&forecasts);
+
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.split("\r\n").all(|l| l.len() <= MAX_LINE));
+        let unfolded = ics.replace("\r\n ", "");
+        assert_eq!(unfolded.matches("BEGIN:VEVENT").count(), 1);
+        assert!(unfolded.contains(&format!("UID:{}\r\n", uid(&name, date))));
+        assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", name)));
+        assert!(unfolded.contains("DTSTART;VALUE=DATE:20210608\r\n"));
+    }
+}
@@ -0,0 +1,50 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+
+///////////////////////////////////////////
+// JSON map file
+//-----------------------------------------
+// recurrence_name => T の JSON (tuning や制約のファイル)
+// ファイルがなければ空とみなす
+///////////////////////////////////////////
+pub fn load<T: DeserializeOwned>(path: &str) -> io::Result<BTreeMap<String, T>> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn save<T: Serialize>(path: &str, map: &BTreeMap<String, T>) -> io::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, map)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_is_empty() {
+        let map: BTreeMap<String, u32> = load("/nonexistent/heron.json").unwrap();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("heron-json-map-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut map = BTreeMap::new();
+        map.insert("seminar".to_string(), vec![1, 2]);
+        save(path, &map).unwrap();
+        let loaded: BTreeMap<String, Vec<u32>> = load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, map);
+    }
+}
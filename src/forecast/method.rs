@@ -0,0 +1,227 @@
+use chrono::{Date, Utc};
+use std::sync::Arc;
+
+use crate::error::HeronError;
+
+use super::fiscal_year::FiscalYear;
+use super::forecaster::{LinearForecaster, LogisticForecaster};
+use super::holiday::HolidayProvider;
+use super::result::Forecast;
+
+///////////////////////////////////////////
+// Forecasting algorithm
+//-----------------------------------------
+// fit     : learn from the occurrence history
+// rank    : candidates for the occurrence
+//           after `events`, best first
+// predict : best candidate of rank
+//           (None if every candidate is rejected)
+//
+// forecast_next  : 次の n 回分
+// forecast_until : until までの全て
+//   フィットし直さずに予測を履歴へ追加して進める．
+//   各ステップは必ず直前の予定より後になり，
+//   MAX_STEPS 回で打ち切る
+///////////////////////////////////////////
+pub trait Forecaster {
+    fn fit(
+        &mut self,
+        events: &Vec<Date<Utc>>,
+        range_recurrence: &Vec<Date<Utc>>,
+        range_candidate: &Vec<i64>,
+    ) -> Result<(), HeronError>;
+
+    fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast;
+
+    fn predict(&self, events: &Vec<Date<Utc>>) -> Option<Date<Utc>> {
+        self.rank(events).best().map(|c| c.date)
+    }
+
+    fn forecast_next(&self, events: &Vec<Date<Utc>>, n: usize) -> Vec<Forecast> {
+        let mut history = events.clone();
+        let mut forecasts = vec![];
+        for _ in 0..n.min(MAX_STEPS) {
+            let (date, ranked) = match advance(self, &history) {
+                Some(f) => f,
+                None => break,
+            };
+            history.push(date);
+            forecasts.push(ranked);
+        }
+        forecasts
+    }
+
+    fn forecast_until(&self, events: &Vec<Date<Utc>>, until: Date<Utc>) -> Vec<Forecast> {
+        let mut history = events.clone();
+        let mut forecasts = vec![];
+        for _ in 0..MAX_STEPS {
+            let (date, ranked) = match advance(self, &history) {
+                Some(f) => f,
+                None => break,
+            };
+            if date > until {
+                break;
+            }
+            history.push(date);
+            forecasts.push(ranked);
+        }
+        forecasts
+    }
+}
+
+pub const MAX_STEPS: usize = 1000;
+
+// 直前の予定より後の候補日のみで 1 ステップ進める
+// 進めた日と，その日を最有力とする予測を返す
+fn advance<F: Forecaster + ?Sized>(
+    forecaster: &F,
+    history: &Vec<Date<Utc>>,
+) -> Option<(Date<Utc>, Forecast)> {
+    let latest = *history.last()?;
+    let ranked = forecaster.rank(history).after(latest)?;
+    let date = ranked.best()?.date;
+    Some((date, ranked))
+}
+
+///////////////////////////////////////////
+// Method options
+//-----------------------------------------
+// holidays    : 祝日の判定
+// max_period  : 検出する周期の上限 (日)
+// fiscal_year : 年度内の位置を揃える基準
+///////////////////////////////////////////
+#[derive(Clone)]
+pub struct Options {
+    pub holidays: Arc<dyn HolidayProvider>,
+    pub max_period: Option<usize>,
+    pub fiscal_year: FiscalYear,
+}
+
+impl Options {
+    pub fn new(holidays: Arc<dyn HolidayProvider>) -> Options {
+        Options {
+            holidays,
+            max_period: None,
+            fiscal_year: FiscalYear::default(),
+        }
+    }
+}
+
+// 登録済みの予測手法 (先頭がデフォルト)
+const METHODS: [&str; 2] = ["linear", "logistic"];
+
+pub const DEFAULT_METHOD: &str = METHODS[0];
+
+pub fn names() -> Vec<&'static str> {
+    METHODS.to_vec()
+}
+
+pub fn new_forecaster(name: &str, options: &Options) -> Option<Box<dyn Forecaster>> {
+    match name {
+        "linear" => Some(Box::new(LinearForecaster::new(options))),
+        "logistic" => Some(Box::new(LogisticForecaster::new(options))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::result::Candidate;
+    use chrono::{Duration, TimeZone};
+
+    // 直前の予定から step 日後の候補日と，それより高いスコアの過去の候補日を返す
+    struct Stepper {
+        step: i64,
+    }
+
+    impl Forecaster for Stepper {
+        fn fit(
+            &mut self,
+            _events: &Vec<Date<Utc>>,
+            _range_recurrence: &Vec<Date<Utc>>,
+            _range_candidate: &Vec<i64>,
+        ) -> Result<(), HeronError> {
+            Ok(())
+        }
+
+        fn rank(&self, events: &Vec<Date<Utc>>) -> Forecast {
+            let latest = *events.last().unwrap();
+            let mut candidates = vec![
+                Candidate::new(latest - Duration::days(3), 2.0, vec![]),
+                Candidate::new(latest, 1.5, vec![]),
+            ];
+            if self.step > 0 {
+                candidates.push(Candidate::new(
+                    latest + Duration::days(self.step),
+                    1.0,
+                    vec![],
+                ));
+            }
+            Forecast::new(candidates)
+        }
+    }
+
+    fn history() -> Vec<Date<Utc>> {
+        vec![Utc.ymd(2021, 4, 7), Utc.ymd(2021, 4, 14)]
+    }
+
+    fn dates(forecasts: &Vec<Forecast>) -> Vec<Date<Utc>> {
+        forecasts.iter().map(|f| f.best().unwrap().date).collect()
+    }
+
+    #[test]
+    fn each_step_is_later_than_the_previous() {
+        let forecasts = Stepper { step: 7 }.forecast_next(&history(), 3);
+        assert_eq!(
+            dates(&forecasts),
+            vec![
+                Utc.ymd(2021, 4, 21),
+                Utc.ymd(2021, 4, 28),
+                Utc.ymd(2021, 5, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_without_a_later_candidate() {
+        let forecaster = Stepper { step: 0 };
+        assert!(forecaster.forecast_next(&history(), 3).is_empty());
+        assert!(forecaster
+            .forecast_until(&history(), Utc.ymd(2022, 4, 1))
+            .is_empty());
+    }
+
+    #[test]
+    fn steps_are_bounded() {
+        let forecaster = Stepper { step: 1 };
+        assert_eq!(
+            forecaster.forecast_next(&history(), MAX_STEPS + 10).len(),
+            MAX_STEPS
+        );
+        assert_eq!(
+            forecaster
+                .forecast_until(&history(), Utc.ymd(2100, 1, 1))
+                .len(),
+            MAX_STEPS
+        );
+    }
+
+    #[test]
+    fn forecast_until_stops_at_until() {
+        let forecaster = Stepper { step: 7 };
+        // 5/19 は until の翌日
+        let forecasts = forecaster.forecast_until(&history(), Utc.ymd(2021, 5, 18));
+        assert_eq!(
+            dates(&forecasts),
+            vec![
+                Utc.ymd(2021, 4, 21),
+                Utc.ymd(2021, 4, 28),
+                Utc.ymd(2021, 5, 5),
+                Utc.ymd(2021, 5, 12)
+            ]
+        );
+        let forecasts = forecaster.forecast_until(&history(), Utc.ymd(2021, 5, 12));
+        assert_eq!(dates(&forecasts).last(), Some(&Utc.ymd(2021, 5, 12)));
+    }
+}
@@ -1 +1,18 @@
+pub mod anchor;
+pub mod backtest;
+pub mod batch;
+pub mod coldstart;
+pub mod constraint;
+pub mod feature;
+pub mod fft;
+pub mod fiscal_year;
 pub mod forecaster;
+pub mod holiday;
+pub mod horizon;
+pub mod ical;
+pub mod json_map;
+pub mod method;
+pub mod periodicity;
+pub mod result;
+pub mod rrule;
+pub mod tuning;
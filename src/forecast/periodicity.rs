@@ -0,0 +1,178 @@
+use chrono::{Date, Utc};
+
+use super::fft;
+
+// 最大ピークに対してこれ未満の強さの周期は捨てる
+const MIN_RELATIVE_STRENGTH: f64 = 0.25;
+// 短い周期の倍数とみなす場合でも，割り引いた自己相関がこの倍率を超えれば別の周期とする
+const HARMONIC_MARGIN: f64 = 1.05;
+const MAX_PERIODS: usize = 5;
+// 周期が検出できない場合
+pub const DEFAULT_PERIOD: usize = 365;
+
+///////////////////////////////////////////
+// Period
+//-----------------------------------------
+// days     : 周期 (日)
+// strength : 最も強い周期を 1 としたときの強さ
+///////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub days: usize,
+    pub strength: f64,
+}
+
+fn dates_to_occurreds(dates: &Vec<Date<Utc>>, range: &Vec<Date<Utc>>) -> Vec<f64> {
+    let len = (range[1] - range[0]).num_days() + 1;
+    let mut occurreds = vec![0.0; len as usize];
+
+    for date in dates.iter() {
+        let j = (*date - range[0]).num_days();
+        if 0 <= j && j < len {
+            occurreds[j as usize] = 1.0;
+        }
+    }
+    occurreds
+}
+
+// ac[lag - 1] = mean(f[lag] * f[0], f[lag + 1] * f[1], ..., f[n - 1] * f[n - 1 - lag])
+fn get_ac(f: &Vec<f64>) -> Vec<f64> {
+    let n = f.len();
+    let r = fft::autocorrelation(f);
+
+    (1..n).map(|lag| r[lag] / (n - lag) as f64).collect()
+}
+
+// q の倍数か (q の 1/10 までのずれは許すので，月単位の周期なら月の長さの違い程度)
+fn is_multiple(p: usize, q: usize) -> bool {
+    let rem = p % q;
+    let tolerance = (q / 10).max(1);
+    rem.min(q - rem) <= tolerance
+}
+
+///////////////////////////////////////////
+// Detect periods
+//-----------------------------------------
+// dates      : occurrence history
+// range      : sampling range
+// max_period : これより長い周期は考えない
+// return     : 有意な周期を強い順に並べたもの
+///////////////////////////////////////////
+// 1. 自己相関を n で割り，重なりの少ない長い周期ほど割り引く
+// 2. 周期の 1/20 の幅で極大になるラグをピークとする
+// 3. 弱いピークと，短い周期の倍数でしかないピークを除く
+///////////////////////////////////////////
+pub fn detect_periods(
+    dates: &Vec<Date<Utc>>,
+    range: &Vec<Date<Utc>>,
+    max_period: Option<usize>,
+) -> Vec<Period> {
+    let series = dates_to_occurreds(dates, range);
+    let ac = get_ac(&series);
+    let n = series.len() as f64;
+    // weighted[lag - 1]
+    let weighted: Vec<f64> = ac
+        .iter()
+        .enumerate()
+        .map(|(i, val)| val * (n - (i + 1) as f64) / n)
+        .collect();
+
+    let mut peaks: Vec<usize> = vec![];
+    let cap = max_period.unwrap_or(usize::MAX);
+    for (i, &val) in weighted.iter().enumerate() {
+        let lag = i + 1;
+        if lag > cap {
+            break;
+        }
+        let width = (lag / 20).max(1);
+        let from = i.saturating_sub(width);
+        let to = (i + width).min(weighted.len() - 1);
+        if val > 0.0 && weighted[from..=to].iter().all(|&x| x <= val) {
+            peaks.push(lag);
+        }
+    }
+
+    let max = peaks
+        .iter()
+        .map(|lag| weighted[lag - 1])
+        .fold(0.0, f64::max);
+    if max <= 0.0 {
+        return vec![Period {
+            days: DEFAULT_PERIOD.min(cap),
+            strength: 0.0,
+        }];
+    }
+
+    let mut periods: Vec<Period> = vec![];
+    for lag in peaks {
+        let strength = weighted[lag - 1] / max;
+        if strength < MIN_RELATIVE_STRENGTH {
+            continue;
+        }
+        let harmonic = periods.iter().any(|p| {
+            is_multiple(lag, p.days) && weighted[lag - 1] <= weighted[p.days - 1] * HARMONIC_MARGIN
+        });
+        if !harmonic {
+            periods.push(Period {
+                days: lag,
+                strength,
+            });
+        }
+    }
+
+    periods.sort_by(|a, b| b.strength.partial_cmp(&a.strength).unwrap());
+    periods.truncate(MAX_PERIODS);
+    periods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    // first から interval 日ごとに n 回
+    fn every(interval: i64, n: i64) -> (Vec<Date<Utc>>, Vec<Date<Utc>>) {
+        let first = Utc.ymd(2021, 4, 5);
+        let dates: Vec<Date<Utc>> = (0..n)
+            .map(|i| first + Duration::days(interval * i))
+            .collect();
+        let range = vec![dates[0], *dates.last().unwrap()];
+        (dates, range)
+    }
+
+    fn days(periods: &Vec<Period>) -> Vec<usize> {
+        periods.iter().map(|p| p.days).collect()
+    }
+
+    #[test]
+    fn weekly_series_has_period_7() {
+        let (dates, range) = every(7, 20);
+        let periods = detect_periods(&dates, &range, None);
+        // 7 日の倍数のラグは 7 日の周期の繰り返しでしかない
+        assert_eq!(days(&periods), vec![7]);
+        assert_eq!(periods[0].strength, 1.0);
+    }
+
+    #[test]
+    fn biweekly_series_has_period_14() {
+        let (dates, range) = every(14, 20);
+        let periods = detect_periods(&dates, &range, None);
+        assert_eq!(days(&periods), vec![14]);
+        assert_eq!(periods[0].strength, 1.0);
+    }
+
+    #[test]
+    fn max_period_caps_detection() {
+        let (dates, range) = every(14, 20);
+        let periods = detect_periods(&dates, &range, Some(10));
+        assert_eq!(days(&periods), vec![DEFAULT_PERIOD.min(10)]);
+    }
+
+    #[test]
+    fn multiples_allow_a_tenth_of_the_period() {
+        assert!(is_multiple(63, 30));
+        assert!(!is_multiple(64, 30));
+        assert!(is_multiple(15, 7));
+        assert!(!is_multiple(17, 7));
+    }
+}
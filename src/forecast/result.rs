@@ -0,0 +1,118 @@
+use chrono::{Date, Utc};
+use std::cmp::Ordering;
+
+use super::coldstart::Fallback;
+use super::constraint::Rejection;
+use super::feature::Feature;
+use super::periodicity::Period;
+
+///////////////////////////////////////////
+// Forecast result
+//-----------------------------------------
+// candidates : 候補日をスコアの高い順に並べたもの
+// periods    : 検出した周期
+// intercept  : 切片 (線形予測子を持つ手法のみ)
+// rejected   : 制約に反して除いた候補日
+// fallback   : 履歴が短いときに使った予測の方法
+//
+// 制約で全ての候補日を除くと candidates は空になる
+///////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub date: Date<Utc>,
+    pub score: f64,
+    // 発生日である確率 (確率モデルの場合のみ)
+    pub probability: Option<f64>,
+    // 1 が最有力
+    pub rank: usize,
+    pub features: Vec<Feature>,
+    // この候補日を生んだ周期と基準日
+    pub period: Option<usize>,
+    pub pivot: Option<Date<Utc>>,
+    // この候補日を生んだ anchor の予定 (anchor を使う場合のみ)
+    pub anchor: Option<Date<Utc>>,
+    // 特徴量ごとのスコアへの寄与
+    pub contributions: Vec<Contribution>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Contribution {
+    pub feature: Feature,
+    pub weight: f64,
+}
+
+impl Candidate {
+    pub fn new(date: Date<Utc>, score: f64, features: Vec<Feature>) -> Candidate {
+        Candidate {
+            date,
+            score,
+            probability: None,
+            rank: 0,
+            features,
+            period: None,
+            pivot: None,
+            anchor: None,
+            contributions: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Forecast {
+    pub candidates: Vec<Candidate>,
+    pub periods: Vec<Period>,
+    pub intercept: Option<f64>,
+    pub rejected: Vec<Rejection>,
+    pub fallback: Option<Fallback>,
+}
+
+impl Forecast {
+    // 同点の場合は先に現れた候補日を優先する
+    pub fn new(mut candidates: Vec<Candidate>) -> Forecast {
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        for (i, candidate) in candidates.iter_mut().enumerate() {
+            candidate.rank = i + 1;
+        }
+
+        Forecast {
+            candidates,
+            periods: vec![],
+            intercept: None,
+            rejected: vec![],
+            fallback: None,
+        }
+    }
+
+    // 候補日がなければ None
+    pub fn best(&self) -> Option<&Candidate> {
+        self.candidates.first()
+    }
+
+    // date より後の候補日だけを残して順位を振り直す
+    pub fn after(&self, date: Date<Utc>) -> Option<Forecast> {
+        let mut candidates: Vec<Candidate> = self
+            .candidates
+            .iter()
+            .filter(|c| c.date > date)
+            .cloned()
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        for (i, candidate) in candidates.iter_mut().enumerate() {
+            candidate.rank = i + 1;
+        }
+
+        Some(Forecast {
+            candidates,
+            periods: self.periods.clone(),
+            intercept: self.intercept,
+            rejected: self.rejected.clone(),
+            fallback: self.fallback,
+        })
+    }
+
+    pub fn top(&self, n: usize) -> &[Candidate] {
+        &self.candidates[..n.min(self.candidates.len())]
+    }
+}
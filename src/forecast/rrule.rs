@@ -0,0 +1,374 @@
+use chrono::prelude::*;
+use chrono::{Date, Duration, Utc};
+use std::fmt;
+
+use super::feature::DayOfWeek;
+
+// BYMONTH の月 1 つあたりの複雑さ (EXDATE 何個分か)
+const MONTH_COST: f64 = 0.1;
+// 試す WEEKLY の INTERVAL の上限
+const MAX_WEEKLY_INTERVAL: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freq {
+    Yearly,
+    Monthly,
+    Weekly,
+}
+
+///////////////////////////////////////////
+// Recurrence rule (RFC 5545 の部分集合)
+//-----------------------------------------
+// freq         : FREQ
+// interval     : INTERVAL (dtstart から数える)
+// by_month     : BYMONTH (空なら全ての月)
+// by_month_day : BYMONTHDAY
+// by_day       : BYDAY
+// by_set_pos   : BYSETPOS (月内の第 n 曜日，-1 は最終)
+// dtstart      : DTSTART
+///////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct RRule {
+    pub freq: Freq,
+    pub interval: u32,
+    pub by_month: Vec<u32>,
+    pub by_month_day: Option<u32>,
+    pub by_day: Option<DayOfWeek>,
+    pub by_set_pos: Option<i32>,
+    pub dtstart: Date<Utc>,
+}
+
+fn days_in_month(date: Date<Utc>) -> u32 {
+    let (y, m) = if date.month() == 12 {
+        (date.year() + 1, 1)
+    } else {
+        (date.year(), date.month() + 1)
+    };
+    Utc.ymd(y, m, 1).pred().day()
+}
+
+fn day_code(weekday: DayOfWeek) -> &'static str {
+    match weekday {
+        DayOfWeek::Mon => "MO",
+        DayOfWeek::Tue => "TU",
+        DayOfWeek::Wed => "WE",
+        DayOfWeek::Thu => "TH",
+        DayOfWeek::Fri => "FR",
+        DayOfWeek::Sat => "SA",
+        DayOfWeek::Sun => "SU",
+    }
+}
+
+// 月内の第 n 曜日 (last なら最終から数えて負)
+fn set_pos(date: Date<Utc>, last: bool) -> i32 {
+    if last {
+        -(((days_in_month(date) - date.day()) / 7 + 1) as i32)
+    } else {
+        ((date.day() - 1) / 7 + 1) as i32
+    }
+}
+
+impl RRule {
+    pub fn matches(&self, date: Date<Utc>) -> bool {
+        if date < self.dtstart {
+            return false;
+        }
+        if !self.by_month.is_empty() && !self.by_month.contains(&date.month()) {
+            return false;
+        }
+        if let Some(d) = self.by_month_day {
+            if date.day() != d {
+                return false;
+            }
+        }
+        if let Some(w) = self.by_day {
+            if DayOfWeek::from(date.weekday()) != w {
+                return false;
+            }
+        }
+        if let Some(n) = self.by_set_pos {
+            if set_pos(date, n < 0) != n {
+                return false;
+            }
+        }
+
+        let interval = self.interval as i64;
+        match self.freq {
+            Freq::Weekly => ((date - self.dtstart).num_days() / 7) % interval == 0,
+            Freq::Monthly => {
+                let months = (date.year() - self.dtstart.year()) as i64 * 12 + date.month() as i64
+                    - self.dtstart.month() as i64;
+                months % interval == 0
+            }
+            Freq::Yearly => (date.year() - self.dtstart.year()) as i64 % interval == 0,
+        }
+    }
+
+    // dtstart から until までの発生日
+    pub fn occurrences(&self, until: Date<Utc>) -> Vec<Date<Utc>> {
+        let len = (until - self.dtstart).num_days();
+        (0..=len)
+            .map(|x| self.dtstart + Duration::days(x))
+            .filter(|d| self.matches(*d))
+            .collect()
+    }
+}
+
+impl fmt::Display for RRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let freq = match self.freq {
+            Freq::Yearly => "YEARLY",
+            Freq::Monthly => "MONTHLY",
+            Freq::Weekly => "WEEKLY",
+        };
+        let mut parts = vec![format!("FREQ={}", freq)];
+        if self.interval > 1 {
+            parts.push(format!("INTERVAL={}", self.interval));
+        }
+        if !self.by_month.is_empty() {
+            let months: Vec<String> = self.by_month.iter().map(|m| m.to_string()).collect();
+            parts.push(format!("BYMONTH={}", months.join(",")));
+        }
+        if let Some(d) = self.by_month_day {
+            parts.push(format!("BYMONTHDAY={}", d));
+        }
+        if let Some(w) = self.by_day {
+            parts.push(format!("BYDAY={}", day_code(w)));
+        }
+        if let Some(n) = self.by_set_pos {
+            parts.push(format!("BYSETPOS={}", n));
+        }
+        write!(f, "{}", parts.join(";"))
+    }
+}
+
+///////////////////////////////////////////
+// Fit of a rule to the history
+//-----------------------------------------
+// exdates     : 規則が生成するが履歴にない日 (EXDATE)
+// rdates      : 履歴にあるが規則が生成しない日 (RDATE)
+// matched     : 規則と履歴の両方にある日の数
+// generated   : 規則が生成した日の数
+// occurrences : 履歴の予定の数
+///////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct Inference {
+    pub rule: RRule,
+    pub exdates: Vec<Date<Utc>>,
+    pub rdates: Vec<Date<Utc>>,
+    pub matched: usize,
+    pub generated: usize,
+    pub occurrences: usize,
+}
+
+impl Inference {
+    fn new(rule: RRule, events: &Vec<Date<Utc>>) -> Inference {
+        let generated = rule.occurrences(*events.last().unwrap());
+        let exdates: Vec<Date<Utc>> = generated
+            .iter()
+            .filter(|d| !events.contains(d))
+            .cloned()
+            .collect();
+        let rdates: Vec<Date<Utc>> = events
+            .iter()
+            .filter(|e| !generated.contains(e))
+            .cloned()
+            .collect();
+
+        Inference {
+            rule,
+            matched: generated.len() - exdates.len(),
+            generated: generated.len(),
+            occurrences: events.len(),
+            exdates,
+            rdates,
+        }
+    }
+
+    // 例外の数と規則の複雑さ (小さいほどよい)
+    pub fn cost(&self) -> f64 {
+        (self.exdates.len() + self.rdates.len()) as f64
+            + MONTH_COST * self.rule.by_month.len() as f64
+    }
+
+    // 履歴のうち規則で再現できた割合
+    pub fn recall(&self) -> f64 {
+        self.matched as f64 / self.occurrences as f64
+    }
+
+    // 規則が生成したうち履歴にあった割合
+    pub fn precision(&self) -> f64 {
+        if self.generated == 0 {
+            return 0.0;
+        }
+        self.matched as f64 / self.generated as f64
+    }
+}
+
+///////////////////////////////////////////
+// Infer RRULE
+//-----------------------------------------
+// 履歴に現れる曜日・第 n 曜日・日・月から規則の候補を作り，
+// EXDATE と RDATE が少ない順に並べる
+//
+// 候補:
+//   FREQ=WEEKLY;INTERVAL=k;BYDAY=wd
+//   FREQ=MONTHLY;BYDAY=wd;BYSETPOS=n
+//   FREQ=MONTHLY;BYMONTHDAY=d
+//   それぞれ BYMONTH (履歴に現れる月) あり・なし．
+//   BYMONTH が 1 つなら FREQ=YEARLY とする
+///////////////////////////////////////////
+// Example
+//-----------------------------------------
+// events : vec!['2019/6/11', '2020/6/9', '2021/6/8']
+// return : FREQ=YEARLY;BYMONTH=6;BYDAY=TU;BYSETPOS=2 (例外なし), ...
+///////////////////////////////////////////
+pub fn infer(events: &Vec<Date<Utc>>) -> Vec<Inference> {
+    let mut events = events.clone();
+    events.sort();
+    events.dedup();
+    let first = match events.first() {
+        Some(d) => *d,
+        None => return vec![],
+    };
+
+    let mut months: Vec<u32> = events.iter().map(|e| e.month()).collect();
+    months.sort();
+    months.dedup();
+    let mut month_sets = vec![vec![]];
+    if months.len() < 12 {
+        month_sets.push(months);
+    }
+
+    let mut weekdays: Vec<DayOfWeek> = events.iter().map(|e| e.weekday().into()).collect();
+    weekdays.sort();
+    weekdays.dedup();
+
+    let mut positions: Vec<(i32, DayOfWeek)> = events
+        .iter()
+        .flat_map(|e| {
+            let w = DayOfWeek::from(e.weekday());
+            vec![(set_pos(*e, false), w), (set_pos(*e, true), w)]
+        })
+        .filter(|(n, _)| *n == -1 || (1..=4).contains(n))
+        .collect();
+    positions.sort();
+    positions.dedup();
+
+    let mut monthdays: Vec<u32> = events.iter().map(|e| e.day()).collect();
+    monthdays.sort();
+    monthdays.dedup();
+
+    let mut rules: Vec<RRule> = vec![];
+    for by_month in month_sets.iter() {
+        let freq = if by_month.len() == 1 {
+            Freq::Yearly
+        } else {
+            Freq::Monthly
+        };
+        for w in weekdays.iter() {
+            let dtstart = *events
+                .iter()
+                .find(|e| DayOfWeek::from(e.weekday()) == *w)
+                .unwrap();
+            for interval in 1..=MAX_WEEKLY_INTERVAL {
+                rules.push(RRule {
+                    freq: Freq::Weekly,
+                    interval,
+                    by_month: by_month.clone(),
+                    by_month_day: None,
+                    by_day: Some(*w),
+                    by_set_pos: None,
+                    dtstart,
+                });
+            }
+        }
+        for (n, w) in positions.iter() {
+            rules.push(RRule {
+                freq,
+                interval: 1,
+                by_month: by_month.clone(),
+                by_month_day: None,
+                by_day: Some(*w),
+                by_set_pos: Some(*n),
+                dtstart: first,
+            });
+        }
+        for d in monthdays.iter() {
+            rules.push(RRule {
+                freq,
+                interval: 1,
+                by_month: by_month.clone(),
+                by_month_day: Some(*d),
+                by_day: None,
+                by_set_pos: None,
+                dtstart: first,
+            });
+        }
+    }
+
+    let mut inferences: Vec<Inference> = rules
+        .into_iter()
+        .map(|rule| Inference::new(rule, &events))
+        .collect();
+    // 同点なら先に作った (単純な) 規則を優先する
+    inferences.sort_by(|a, b| a.cost().partial_cmp(&b.cost()).unwrap());
+    inferences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(freq: Freq, interval: u32, by_day: DayOfWeek, by_set_pos: Option<i32>) -> RRule {
+        RRule {
+            freq,
+            interval,
+            by_month: vec![],
+            by_month_day: None,
+            by_day: Some(by_day),
+            by_set_pos,
+            dtstart: Utc.ymd(2021, 4, 12),
+        }
+    }
+
+    // 規則が生成した日から同じ規則を推定できる
+    fn assert_round_trip(known: RRule, rrule: &str) {
+        let events = known.occurrences(Utc.ymd(2022, 3, 31));
+        let best = &infer(&events)[0];
+        assert_eq!(best.rule.to_string(), rrule);
+        assert_eq!(best.rule.occurrences(*events.last().unwrap()), events);
+        assert!(best.exdates.is_empty() && best.rdates.is_empty());
+    }
+
+    #[test]
+    fn monthly_by_set_pos_round_trip() {
+        assert_round_trip(
+            rule(Freq::Monthly, 1, DayOfWeek::Wed, Some(2)),
+            "FREQ=MONTHLY;BYDAY=WE;BYSETPOS=2",
+        );
+        assert_round_trip(
+            rule(Freq::Monthly, 1, DayOfWeek::Fri, Some(-1)),
+            "FREQ=MONTHLY;BYDAY=FR;BYSETPOS=-1",
+        );
+    }
+
+    #[test]
+    fn biweekly_round_trip() {
+        assert_round_trip(
+            rule(Freq::Weekly, 2, DayOfWeek::Mon, None),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO",
+        );
+    }
+
+    #[test]
+    fn skipped_occurrence_becomes_exdate() {
+        let known = rule(Freq::Monthly, 1, DayOfWeek::Wed, Some(2));
+        let mut events = known.occurrences(Utc.ymd(2022, 3, 31));
+        let skipped = events.remove(4);
+        let best = &infer(&events)[0];
+        assert_eq!(best.rule.to_string(), "FREQ=MONTHLY;BYDAY=WE;BYSETPOS=2");
+        assert_eq!(best.exdates, vec![skipped]);
+        assert!(best.rdates.is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{Date, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+
+use super::backtest::{Backtest, Evaluation};
+use super::fiscal_year::FiscalYear;
+use super::json_map;
+use super::method::{self, Options};
+
+// 探索するハイパーパラメータ
+const CANDIDATE_WIDTHS: [i64; 6] = [3, 5, 7, 9, 11, 15];
+const SAMPLING_YEARS: [Option<u32>; 5] = [None, Some(1), Some(2), Some(3), Some(5)];
+const MAX_PERIODS: [Option<usize>; 5] = [None, Some(14), Some(35), Some(100), Some(400)];
+// tune の --holdout を省略したとき．組み合わせを比べるので evaluate より多く隠す
+pub const DEFAULT_HOLDOUT: usize = 5;
+
+///////////////////////////////////////////
+// Tuned configuration of a recurrence
+//-----------------------------------------
+// candidate_width : 候補日の数 (奇数)
+// sampling_years  : 直近何年度分で学習するか (None なら全履歴)
+// max_period      : 検出する周期の上限 (None なら上限なし)
+// mae, within_rate: 採用時のバックテスト結果
+///////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    pub method: String,
+    pub candidate_width: i64,
+    pub sampling_years: Option<u32>,
+    pub max_period: Option<usize>,
+    pub mae: f64,
+    pub within_rate: f64,
+}
+
+impl Tuning {
+    pub fn range_candidate(&self) -> Vec<i64> {
+        let n = (self.candidate_width - 1) / 2;
+        (-n..=n).collect()
+    }
+}
+
+///////////////////////////////////////////
+// Grid search
+//-----------------------------------------
+// 全ての組み合わせでバックテストを行い，平均絶対誤差が
+// 最小 (同じなら的中率が最大) のものを選ぶ．
+// 同点なら先に試した (候補日の少ない) 方を残す
+///////////////////////////////////////////
+pub fn tune(
+    method_name: &str,
+    options: &Options,
+    events: &Vec<Date<Utc>>,
+    fiscal_year: FiscalYear,
+    holdout: usize,
+    tolerance: i64,
+) -> Option<Tuning> {
+    let years = (fiscal_year.year_of(*events.last()?) - fiscal_year.year_of(events[0]) + 1) as u32;
+    let mut best: Option<Tuning> = None;
+
+    for &max_period in MAX_PERIODS.iter() {
+        let mut options = options.clone();
+        options.max_period = max_period;
+        let mut forecaster = method::new_forecaster(method_name, &options)?;
+
+        for &sampling_years in SAMPLING_YEARS.iter() {
+            // 全履歴と同じになるものは除く
+            if let Some(y) = sampling_years {
+                if y >= years {
+                    continue;
+                }
+            }
+            for &candidate_width in CANDIDATE_WIDTHS.iter() {
+                let n = (candidate_width - 1) / 2;
+                let backtest = Backtest {
+                    holdout,
+                    tolerance,
+                    range_candidate: (-n..=n).collect(),
+                    fiscal_year,
+                    sampling_years,
+                };
+                // 回帰が解けない組み合わせは候補から外す
+                let trials = match backtest.run(forecaster.as_mut(), events) {
+                    Ok(t) => t,
+                    Err(_) => continue,
+                };
+                if trials.is_empty() {
+                    return None;
+                }
+                let e = Evaluation::new("", method_name, &trials, tolerance);
+
+                let better = match &best {
+                    None => true,
+                    Some(b) => {
+                        e.mae < b.mae - 1e-9
+                            || (e.mae < b.mae + 1e-9 && e.within_rate > b.within_rate + 1e-9)
+                    }
+                };
+                if better {
+                    best = Some(Tuning {
+                        method: method_name.to_string(),
+                        candidate_width,
+                        sampling_years,
+                        max_period,
+                        mae: e.mae,
+                        within_rate: e.within_rate,
+                    });
+                }
+            }
+        }
+    }
+    best
+}
+
+///////////////////////////////////////////
+// Tuning file
+//-----------------------------------------
+// recurrence_name => Tuning の JSON
+// ファイルがなければ空とみなす
+///////////////////////////////////////////
+pub fn load(path: &str) -> io::Result<BTreeMap<String, Tuning>> {
+    json_map::load(path)
+}
+
+pub fn save(path: &str, tunings: &BTreeMap<String, Tuning>) -> io::Result<()> {
+    json_map::save(path, tunings)
+}
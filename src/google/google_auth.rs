@@ -14,6 +14,8 @@ use std::fs::File;
 use std::io;
 use std::io::BufReader;
 
+use crate::error::HeronError;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccessTokenResponse {
     pub access_token: String,
@@ -45,12 +47,12 @@ fn gets() -> String {
     code
 }
 
-fn client_generate(credentials: Credentials) -> BasicClient {
+fn client_generate(credentials: Credentials) -> Result<BasicClient, HeronError> {
     let client = BasicClient::new(
         ClientId::new(credentials.client_id),
         Some(ClientSecret::new(credentials.client_secret)),
-        AuthUrl::new(credentials.auth_uri).unwrap(),
-        Some(TokenUrl::new(credentials.token_uri).unwrap()),
+        AuthUrl::new(credentials.auth_uri).map_err(|e| HeronError::Auth(e.to_string()))?,
+        Some(TokenUrl::new(credentials.token_uri).map_err(|e| HeronError::Auth(e.to_string()))?),
     )
     .set_redirect_uri(
         RedirectUrl::new("urn:ietf:wg:oauth:2.0:oob".to_string()).expect("Invalid redirect URL"),
@@ -60,12 +62,12 @@ fn client_generate(credentials: Credentials) -> BasicClient {
             .expect("Invalid revocation endpoint URL"),
     );
 
-    client
+    Ok(client)
 }
 
-pub fn authorize(file: String) {
-    let credentials = Credentials::new(file);
-    let client = client_generate(credentials);
+pub fn authorize(file: String) -> Result<(), HeronError> {
+    let credentials = Credentials::new(file)?;
+    let client = client_generate(credentials)?;
     let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
     let (authorize_url, _csrf_state) = client
         .authorize_url(CsrfToken::new_random)
@@ -88,27 +90,31 @@ pub fn authorize(file: String) {
         .exchange_code(AuthorizationCode::new(code))
         .set_pkce_verifier(pkce_code_verifier)
         .request(http_client)
-        .unwrap();
+        .map_err(|e| HeronError::Auth(e.to_string()))?;
 
-    let file = File::create("token.json".to_string()).unwrap();
+    let file = File::create("token.json".to_string())
+        .map_err(|e| HeronError::Auth(format!("token.json: {}", e)))?;
     match serde_json::to_writer(file, &token_result) {
         Ok(_) => println!("Authorize Success!"),
-        Err(_) => println!("Fatal Authorize"),
+        Err(e) => return Err(HeronError::Auth(format!("token.json: {}", e))),
     }
+    Ok(())
 }
 
-pub fn get_access_token() -> AccessTokenResponse {
+pub fn get_access_token() -> Result<AccessTokenResponse, HeronError> {
     match File::open("token.json".to_string()) {
         Ok(_) => (),
-        Err(_) => authorize("credentials.json".to_string()),
+        Err(_) => authorize("credentials.json".to_string())?,
     }
 
-    let file = File::open("token.json".to_string()).unwrap();
+    let file = File::open("token.json".to_string())
+        .map_err(|e| HeronError::Auth(format!("token.json: {}", e)))?;
     let reader = BufReader::new(file);
 
-    let token: AccessTokenResponse = serde_json::from_reader(reader).unwrap();
+    let token: AccessTokenResponse = serde_json::from_reader(reader)
+        .map_err(|e| HeronError::Auth(format!("token.json: {}", e)))?;
 
-    token
+    Ok(token)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -123,11 +129,13 @@ struct Credentials {
 }
 
 impl Credentials {
-    fn new(filepath: String) -> Credentials {
-        let file = File::open(filepath).unwrap();
+    fn new(filepath: String) -> Result<Credentials, HeronError> {
+        let file =
+            File::open(&filepath).map_err(|e| HeronError::Auth(format!("{}: {}", filepath, e)))?;
         let reader = BufReader::new(file);
 
-        let config: Credentials = serde_json::from_reader(reader).unwrap();
-        config
+        let config: Credentials = serde_json::from_reader(reader)
+            .map_err(|e| HeronError::Auth(format!("{}: {}", filepath, e)))?;
+        Ok(config)
     }
 }
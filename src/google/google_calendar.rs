@@ -3,16 +3,21 @@ extern crate google_calendar3;
 extern crate reqwest;
 extern crate serde;
 
+use chrono::NaiveDate;
 use google_calendar3::Event;
 use reqwest::header;
 use serde::{Deserialize, Serialize};
+use std::env;
 
 use crate::google::google_auth;
-use crate::google_auth::AccessTokenResponse;
+use crate::error::HeronError;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CalendarEvent {
     pub items: Vec<Event>,
+    // 続きのページがあるときだけ返る
+    #[serde(rename = "nextPageToken", default, skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,27 +41,12 @@ pub struct OriginalStartTime {
     pub date_time: String,
 }
 
-#[test]
-fn print_typename<T>(_: T) {
-    println!("{}", std::any::type_name::<T>());
-}
-
-pub fn get_oneday_schedule(email: String, recurrence: String) -> CalendarEvent {
-    let token: AccessTokenResponse = google_auth::get_access_token();
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        header::AUTHORIZATION,
-        header::HeaderValue::from_str(&format!("OAuth {}", token.access_token)).unwrap(),
-    );
-
-    let response = reqwest::blocking::ClientBuilder::new()
-        .default_headers(headers.clone())
-        .build()
-        .unwrap()
-        .get(&format!(
-            "https://www.googleapis.com/calendar/v3/calendars/{}/events",
-            email
-        ))
+pub fn get_oneday_schedule(
+    email: String,
+    recurrence: String,
+) -> Result<CalendarEvent, HeronError> {
+    let request = client()?
+        .get(&format!("{}/calendars/{}/events", api_base(), email))
         .query(&[
             ("timeZone", "jst"),
 	    ("sharedExtendedProperty",
@@ -67,15 +57,443 @@ pub fn get_oneday_schedule(email: String, recurrence: String) -> CalendarEvent {
 	    ("singleEvents", "true")
             // ("timeMin", &oneday.and_hms(0, 0, 0).to_rfc3339()),
             // ("timeMax", &oneday.and_hms(23, 59, 59).to_rfc3339()),
-        ])
-        .send()
-        .unwrap()
-        .text()
-        .unwrap();
+        ]);
+    let response = send(request)?;
 
-    serde_json::from_str(&response).unwrap()
+    serde_json::from_str(&response).map_err(|e| HeronError::Api(e.to_string()))
 }
 
 // pub fn get_today_schedule(email: String) -> CalendarEvent {
 //     get_oneday_schedule(email, )
 // }
+
+///////////////////////////////////////////
+// Calendar API endpoint
+//-----------------------------------------
+// HERON_CALENDAR_API があればそちらを使う
+// (ローカルのモックサーバで試すため)
+///////////////////////////////////////////
+pub const DEFAULT_API_BASE: &str = "https://www.googleapis.com/calendar/v3";
+
+pub fn api_base() -> String {
+    env::var("HERON_CALENDAR_API")
+        .map(|b| b.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_API_BASE.to_string())
+}
+
+// HERON_ACCESS_TOKEN があれば token.json の代わりに使う
+fn access_token() -> Result<String, HeronError> {
+    match env::var("HERON_ACCESS_TOKEN") {
+        Ok(t) => Ok(t),
+        Err(_) => google_auth::get_access_token().map(|t| t.access_token),
+    }
+}
+
+fn client() -> Result<reqwest::blocking::Client, HeronError> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        header::HeaderValue::from_str(&format!("OAuth {}", access_token()?))
+            .map_err(|e| HeronError::Auth(e.to_string()))?,
+    );
+    reqwest::blocking::ClientBuilder::new()
+        .default_headers(headers)
+        .build()
+        .map_err(|e| HeronError::Api(e.to_string()))
+}
+
+// 401 と 403 は認証の失敗，それ以外の失敗は API のエラーとする
+fn send(request: reqwest::blocking::RequestBuilder) -> Result<String, HeronError> {
+    let response = request
+        .send()
+        .map_err(|e| HeronError::Api(e.to_string()))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(HeronError::Auth(status.to_string()));
+    }
+    response
+        .error_for_status()
+        .and_then(|r| r.text())
+        .map_err(|e| HeronError::Api(e.to_string()))
+}
+
+///////////////////////////////////////////
+// Forecast events
+//-----------------------------------------
+// 予測は終日の仮の予定 (tentative) として書き込み，
+// 元の予定と同じ sharedExtendedProperty の recurrence_name と
+// 予測であることを示す FORECAST_MARKER を付ける
+///////////////////////////////////////////
+pub const FORECAST_MARKER: &str = "heron_forecast";
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SyncResult {
+    pub inserted: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub kept: usize,
+}
+
+fn forecast_body(recurrence: &str, date: NaiveDate) -> serde_json::Value {
+    serde_json::json!({
+        "summary": recurrence,
+        "start": { "date": date.format("%Y-%m-%d").to_string() },
+        "end": { "date": date.succ().format("%Y-%m-%d").to_string() },
+        "status": "tentative",
+        "transparency": "transparent",
+        "extendedProperties": {
+            "shared": {
+                "recurrence_name": recurrence,
+                FORECAST_MARKER: "true",
+            }
+        }
+    })
+}
+
+// heron が書き込んだ予測か
+pub fn is_forecast(event: &Event) -> bool {
+    event
+        .extended_properties
+        .as_ref()
+        .and_then(|p| p.shared.as_ref())
+        .map_or(false, |shared| shared.contains_key(FORECAST_MARKER))
+}
+
+fn event_date(event: &Event) -> Option<NaiveDate> {
+    let date = event.start.as_ref()?.date.as_ref()?;
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+// 書き込み済みの予測 (日付順)
+// maxResults を超える分は nextPageToken をたどって全て取得する
+pub fn get_forecast_events(calendar_id: &str, recurrence: &str) -> Result<Vec<Event>, HeronError> {
+    let client = client()?;
+    let mut items = vec![];
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut query = vec![
+            (
+                "sharedExtendedProperty",
+                format!("recurrence_name={}", recurrence),
+            ),
+            (
+                "sharedExtendedProperty",
+                format!("{}=true", FORECAST_MARKER),
+            ),
+            ("maxResults", "2000".to_string()),
+            ("orderBy", "starttime".to_string()),
+            ("singleEvents", "true".to_string()),
+        ];
+        if let Some(token) = page_token {
+            query.push(("pageToken", token));
+        }
+        let response = send(
+            client
+                .get(&format!("{}/calendars/{}/events", api_base(), calendar_id))
+                .query(&query),
+        )?;
+        let page: CalendarEvent =
+            serde_json::from_str(&response).map_err(|e| HeronError::Api(e.to_string()))?;
+        items.extend(page.items);
+        page_token = match page.next_page_token {
+            Some(token) => Some(token),
+            None => break,
+        };
+    }
+    items.sort_by_key(|e| event_date(e));
+    Ok(items)
+}
+
+///////////////////////////////////////////
+// Write forecasts
+//-----------------------------------------
+// calendar_id : 書き込み先
+// recurrence  : リカーレンス名
+// dates       : 予測した日
+//
+// 1. 同じ日の予測が既にあれば内容を更新する
+// 2. 残った既存の予測は日付順に新しい日へ移す
+// 3. それでも残った既存の予測は古いので消す
+// 4. 足りない分は新しく作る
+// 何度実行しても予測が重複しない
+///////////////////////////////////////////
+pub fn write_forecasts(
+    calendar_id: &str,
+    recurrence: &str,
+    dates: &Vec<NaiveDate>,
+) -> Result<SyncResult, HeronError> {
+    let client = client()?;
+    let events_url = format!("{}/calendars/{}/events", api_base(), calendar_id);
+    let mut result = SyncResult::default();
+
+    let mut existing = get_forecast_events(calendar_id, recurrence)?;
+    let mut pending: Vec<NaiveDate> = vec![];
+    for date in dates.iter() {
+        match existing.iter().position(|e| event_date(e) == Some(*date)) {
+            Some(i) => {
+                let event = existing.remove(i);
+                let id = event.id.unwrap_or_default();
+                send(
+                    client
+                        .patch(&format!("{}/{}", events_url, id))
+                        .json(&forecast_body(recurrence, *date)),
+                )?;
+                result.kept += 1;
+            }
+            None => pending.push(*date),
+        }
+    }
+
+    let mut stale = existing.into_iter();
+    for date in pending {
+        match stale.next() {
+            Some(event) => {
+                let id = event.id.unwrap_or_default();
+                send(
+                    client
+                        .patch(&format!("{}/{}", events_url, id))
+                        .json(&forecast_body(recurrence, date)),
+                )?;
+                result.updated += 1;
+            }
+            None => {
+                send(
+                    client
+                        .post(&events_url)
+                        .json(&forecast_body(recurrence, date)),
+                )?;
+                result.inserted += 1;
+            }
+        }
+    }
+    for event in stale {
+        let id = event.id.unwrap_or_default();
+        send(client.delete(&format!("{}/{}", events_url, id)))?;
+        result.deleted += 1;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    // ページングを試すため 1 ページの件数を小さくする
+    const PAGE_SIZE: usize = 2;
+
+    // Calendar API のモック (予定はメモリに持つ)
+    #[derive(Default)]
+    struct Mock {
+        events: BTreeMap<String, Value>,
+        next_id: usize,
+        requests: Vec<String>,
+    }
+
+    impl Mock {
+        fn insert(&mut self, mut event: Value) -> Value {
+            self.next_id += 1;
+            let id = format!("e{}", self.next_id);
+            event["id"] = json!(id);
+            self.events.insert(id, event.clone());
+            event
+        }
+
+        fn respond(&mut self, method: &str, target: &str, body: &[u8]) -> String {
+            let (path, query) = match target.find('?') {
+                Some(i) => (&target[..i], &target[i + 1..]),
+                None => (target, ""),
+            };
+            self.requests.push(method.to_string());
+            let id = path.rsplit('/').next().unwrap_or("").to_string();
+            match method {
+                "GET" => self.list(query),
+                "POST" => self
+                    .insert(serde_json::from_slice(body).unwrap())
+                    .to_string(),
+                "PATCH" => {
+                    let patch: Value = serde_json::from_slice(body).unwrap();
+                    let event = self.events.get_mut(&id).unwrap();
+                    for (k, v) in patch.as_object().unwrap() {
+                        event[k] = v.clone();
+                    }
+                    event.to_string()
+                }
+                "DELETE" => {
+                    self.events.remove(&id).unwrap();
+                    String::new()
+                }
+                _ => panic!("unexpected method: {}", method),
+            }
+        }
+
+        // sharedExtendedProperty で絞り込み，pageToken は読み飛ばす件数
+        fn list(&self, query: &str) -> String {
+            let mut filters = vec![];
+            let mut offset = 0;
+            for pair in query.split('&') {
+                let mut kv = pair.splitn(2, '=');
+                let key = kv.next().unwrap_or("");
+                let value = decode(kv.next().unwrap_or(""));
+                match key {
+                    "sharedExtendedProperty" => filters.push(value),
+                    "pageToken" => offset = value.parse().unwrap(),
+                    _ => {}
+                }
+            }
+            let matched: Vec<&Value> = self
+                .events
+                .values()
+                .filter(|e| {
+                    filters.iter().all(|f| {
+                        let mut kv = f.splitn(2, '=');
+                        let key = kv.next().unwrap();
+                        e["extendedProperties"]["shared"][key] == json!(kv.next().unwrap())
+                    })
+                })
+                .collect();
+            let mut page = json!({
+                "items": matched.iter().skip(offset).take(PAGE_SIZE).collect::<Vec<_>>()
+            });
+            if offset + PAGE_SIZE < matched.len() {
+                page["nextPageToken"] = json!((offset + PAGE_SIZE).to_string());
+            }
+            page.to_string()
+        }
+
+        fn forecast_dates(&self) -> Vec<String> {
+            let mut dates: Vec<String> = self
+                .events
+                .values()
+                .filter(|e| e["extendedProperties"]["shared"][FORECAST_MARKER] == json!("true"))
+                .map(|e| e["start"]["date"].as_str().unwrap().to_string())
+                .collect();
+            dates.sort();
+            dates
+        }
+
+        fn count(&self, method: &str) -> usize {
+            self.requests.iter().filter(|m| *m == method).count()
+        }
+    }
+
+    fn decode(s: &str) -> String {
+        let bytes = s.as_bytes();
+        let mut out = vec![];
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' => {
+                    out.push(u8::from_str_radix(&s[i + 1..i + 3], 16).unwrap());
+                    i += 3;
+                }
+                b'+' => {
+                    out.push(b' ');
+                    i += 1;
+                }
+                b => {
+                    out.push(b);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    // 1 接続で複数のリクエストを受ける (keep-alive)
+    fn handle(stream: TcpStream, mock: Arc<Mutex<Mock>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap_or("").to_string();
+            let target = parts.next().unwrap_or("").to_string();
+
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                let header = header.trim_end().to_ascii_lowercase();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some(v) = header.strip_prefix("content-length:") {
+                    length = v.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response = mock.lock().unwrap().respond(&method, &target, &body);
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    }
+
+    fn serve(mock: Arc<Mutex<Mock>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mock = mock.clone();
+                thread::spawn(move || handle(stream.unwrap(), mock));
+            }
+        });
+        base
+    }
+
+    fn ymd(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn write_forecasts_syncs_without_duplicates() {
+        let mock = Arc::new(Mutex::new(Mock::default()));
+        env::set_var("HERON_CALENDAR_API", serve(mock.clone()));
+        env::set_var("HERON_ACCESS_TOKEN", "dummy");
+
+        // 元の予定は予測ではないので触らない
+        mock.lock().unwrap().insert(json!({
+            "summary": "seminar",
+            "start": { "date": "2021-04-14" },
+            "extendedProperties": { "shared": { "recurrence_name": "seminar" } }
+        }));
+
+        let dates = vec![ymd("2021-05-12"), ymd("2021-06-09"), ymd("2021-07-14")];
+        let r = write_forecasts("cal", "seminar", &dates).unwrap();
+        assert_eq!((r.inserted, r.updated, r.deleted, r.kept), (3, 0, 0, 0));
+
+        // 同じ予測を書き直しても増えない (既存の予測は 2 ページにまたがる)
+        let r = write_forecasts("cal", "seminar", &dates).unwrap();
+        assert_eq!((r.inserted, r.updated, r.deleted, r.kept), (0, 0, 0, 3));
+        assert_eq!(
+            mock.lock().unwrap().forecast_dates(),
+            vec!["2021-05-12", "2021-06-09", "2021-07-14"]
+        );
+
+        // 予測が減れば 1 件を移し，残りの古い予測は消す
+        let r = write_forecasts("cal", "seminar", &vec![ymd("2021-06-16")]).unwrap();
+        assert_eq!((r.inserted, r.updated, r.deleted, r.kept), (0, 1, 2, 0));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.forecast_dates(), vec!["2021-06-16"]);
+        assert_eq!(mock.count("DELETE"), 2);
+        assert_eq!(mock.count("POST"), 3);
+        assert_eq!(mock.events.len(), 2);
+    }
+}
@@ -0,0 +1,121 @@
+use chrono::prelude::*;
+use chrono::{Date, NaiveDate, Utc};
+use std::fs;
+
+use crate::error::HeronError;
+use crate::forecast::batch::{self, Series};
+use crate::google::google_calendar;
+
+///////////////////////////////////////////
+// 1 つのリカーレンスの入力
+//-----------------------------------------
+// 1 行に "YYYY-MM-DD" を書く
+// "EOF" の行があればそこで打ち切る
+// 予定は日付順に並べ，同じ日の重複は除く
+///////////////////////////////////////////
+pub fn parse_dates(lines: &Vec<String>) -> Result<Vec<Date<Utc>>, HeronError> {
+    let mut events = vec![];
+
+    for (i, line) in lines.iter().enumerate() {
+        if line == "EOF" {
+            break;
+        }
+        match NaiveDate::parse_from_str(line.trim(), "%Y-%m-%d") {
+            Ok(d) => events.push(Date::from_utc(d, Utc)),
+            Err(_) => {
+                return Err(HeronError::Parse {
+                    path: None,
+                    line: i + 1,
+                    message: format!("invalid date: {}", line),
+                })
+            }
+        }
+    }
+    Ok(sorted(events))
+}
+
+// 予測は履歴が日付順で重複がないことを前提にするので，入力の時点で揃える
+fn sorted(mut events: Vec<Date<Utc>>) -> Vec<Date<Utc>> {
+    events.sort();
+    events.dedup();
+    events
+}
+
+// "名前,日付" のファイル (batch::parse_series の形式) を読む
+pub fn read_series_file(path: &str) -> Result<Vec<Series>, HeronError> {
+    let text =
+        fs::read_to_string(path).map_err(|e| HeronError::Input(format!("{}: {}", path, e)))?;
+    let lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+    batch::parse_series(&lines).map_err(|e| e.in_file(path))
+}
+
+///////////////////////////////////////////
+// 予定履歴の取得元
+//-----------------------------------------
+// GoogleCalendar : カレンダーを予定名で検索する
+// Vec<Series>    : 読み込み済みの series から探す
+// いずれも日付順で重複のない履歴を返す
+///////////////////////////////////////////
+pub trait CalendarSource {
+    fn events(&self, recurrence_name: &str) -> Result<Vec<Date<Utc>>, HeronError>;
+}
+
+pub struct GoogleCalendar {
+    pub calendar_id: String,
+}
+
+impl GoogleCalendar {
+    pub fn new(calendar_id: &str) -> GoogleCalendar {
+        GoogleCalendar {
+            calendar_id: calendar_id.to_string(),
+        }
+    }
+}
+
+impl CalendarSource for GoogleCalendar {
+    fn events(&self, recurrence_name: &str) -> Result<Vec<Date<Utc>>, HeronError> {
+        let events_list = google_calendar::get_oneday_schedule(
+            self.calendar_id.clone(),
+            recurrence_name.to_string(),
+        )?;
+        // 書き戻した予測は履歴に含めない
+        let events = events_list
+            .items
+            .iter()
+            .filter(|i| !google_calendar::is_forecast(i))
+            .map(|i| {
+                let start = i.start.as_ref();
+                match (
+                    start.and_then(|s| s.date_time.as_ref()),
+                    start.and_then(|s| s.date.as_ref()),
+                ) {
+                    (Some(d), _) => d
+                        .parse::<DateTime<Utc>>()
+                        .map(|d| d.date())
+                        .map_err(|_| HeronError::Api(format!("invalid dateTime: {}", d))),
+                    (None, Some(d)) => NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                        .map(|d| Date::from_utc(d, Utc))
+                        .map_err(|_| HeronError::Api(format!("invalid date: {}", d))),
+                    // 開始日のない予定は履歴にできない
+                    (None, None) => Err(HeronError::Api(format!(
+                        "event without start: {}",
+                        i.id.as_ref().map_or("(no id)", |id| id.as_str())
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, HeronError>>()?;
+        Ok(sorted(events))
+    }
+}
+
+impl CalendarSource for Vec<Series> {
+    fn events(&self, recurrence_name: &str) -> Result<Vec<Date<Utc>>, HeronError> {
+        match self.iter().find(|s| s.name == recurrence_name) {
+            Some(s) => Ok(s.events.clone()),
+            None => Err(HeronError::Input(format!(
+                "unknown recurrence: {}",
+                recurrence_name
+            ))),
+        }
+    }
+}
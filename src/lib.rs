@@ -0,0 +1,31 @@
+//////////////////////////////////////////////////////////
+// heron: 繰り返し予定の次回の日付を予測する
+//-----------------------------------------
+// history  : 予定履歴の入力と取得元 (CalendarSource)
+// settings : 1 つのリカーレンスの予測設定と forecast_series
+// forecast : 予測手法と予測結果
+// google   : Google Calendar API
+// error    : HeronError
+//
+// Example
+//   let holidays = forecast::holiday::from_spec("jp").map_err(HeronError::Input)?;
+//   let settings = Settings::new(Options::new(holidays));
+//   let events = GoogleCalendar::new(calendar_id).events("seminar")?;
+//   let forecasts = forecast_series(Some("seminar"), &events, &settings)?;
+//   if let Some(best) = forecasts[0].best() {
+//       println!("{}", best.date);
+//   }
+//////////////////////////////////////////////////////////
+pub mod error;
+pub mod forecast;
+pub mod google;
+pub mod history;
+pub mod settings;
+
+pub use self::error::HeronError;
+pub use self::forecast::batch::Series;
+pub use self::forecast::fiscal_year::FiscalYear;
+pub use self::forecast::method::{Forecaster, Options};
+pub use self::forecast::result::{Candidate, Forecast};
+pub use self::history::{CalendarSource, GoogleCalendar};
+pub use self::settings::{forecast_series, Settings};
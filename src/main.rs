@@ -1,40 +1,573 @@
-#[macro_use]
-extern crate polars;
-
 #[macro_use]
 extern crate clap;
 
-mod forecast;
-mod google;
-
-use self::forecast::forecaster;
-use self::google::google_auth;
+use heron::forecast::backtest::{self, Backtest, Evaluation};
+use heron::forecast::batch::{self, Report, Series};
+use heron::forecast::constraint::{self, Blackout, Constraints};
+use heron::forecast::feature::{DayOfWeek, Locale};
+use heron::forecast::fiscal_year::FiscalYear;
+use heron::forecast::holiday::{self, HolidayProvider};
+use heron::forecast::horizon::Horizon;
+use heron::forecast::ical;
+use heron::forecast::method::{self, Forecaster};
+use heron::forecast::result::Forecast;
+use heron::forecast::rrule::{self, Inference};
+use heron::forecast::tuning;
+use heron::google;
+use heron::history::{self, CalendarSource, GoogleCalendar};
+use heron::settings::{forecast_series, Settings};
+use heron::HeronError;
 
 use chrono::prelude::*;
 use chrono::{NaiveDate, Utc};
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use std::collections::BTreeMap;
 use std::env;
+use std::fs;
 use std::io::{self, BufRead};
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+
+fn print_candidates(forecast: &Forecast, n: usize, locale: Locale) {
+    for candidate in forecast.top(n) {
+        let probability = match candidate.probability {
+            Some(p) => format!(" p={:.4}", p),
+            None => "".to_string(),
+        };
+        println!(
+            "  {:>2}: {} score={:.4}{} [{}]",
+            candidate.rank,
+            candidate.date.format("%Y-%m-%d"),
+            candidate.score,
+            probability,
+            candidate
+                .features
+                .iter()
+                .map(|f| f.label(locale))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+}
+
+fn print_forecast(forecast: &Forecast, top: usize, locale: Locale) {
+    if let Some(fallback) = forecast.fallback {
+        eprintln!("warning: {}", fallback);
+    }
+    let best = match forecast.best() {
+        Some(b) => b,
+        None => {
+            println!("forecast: none");
+            return;
+        }
+    };
+    println!("forecast: {:?}", best.date);
+    if let Some(p) = best.probability {
+        println!("probability: {:.4}", p);
+    }
+    print_candidates(forecast, top, locale);
+}
+
+fn print_explanation(forecast: &Forecast, n: usize, locale: Locale) {
+    let periods: Vec<String> = forecast
+        .periods
+        .iter()
+        .map(|p| format!("{} days ({:.2})", p.days, p.strength))
+        .collect();
+    println!("periods: {}", periods.join(", "));
+    if !forecast.rejected.is_empty() {
+        let rejected: Vec<String> = forecast
+            .rejected
+            .iter()
+            .map(|r| format!("{} ({})", r.date.format("%Y-%m-%d"), r.violation))
+            .collect();
+        println!("rejected: {}", rejected.join(", "));
+    }
+
+    for candidate in forecast.top(n) {
+        let mut origin = String::new();
+        if let (Some(period), Some(pivot)) = (candidate.period, candidate.pivot) {
+            origin = format!(
+                " (period {} days, pivot {})",
+                period,
+                pivot.format("%Y-%m-%d")
+            );
+        }
+        if let Some(anchor) = candidate.anchor {
+            origin += &format!(
+                " (anchor {} {:+} days)",
+                anchor.format("%Y-%m-%d"),
+                (candidate.date - anchor).num_days()
+            );
+        }
+        // 確率モデルの寄与は対数オッズの内訳なので，確率と並べて logit も表示する
+        let score = match candidate.probability {
+            Some(p) => {
+                let logit = forecast.intercept.unwrap_or(0.0)
+                    + candidate
+                        .contributions
+                        .iter()
+                        .map(|c| c.weight)
+                        .sum::<f64>();
+                format!("probability={:.4} logit={:+.4}", p, logit)
+            }
+            None => format!("score={:.4}", candidate.score),
+        };
+        println!(
+            "  {:>2}: {} {}{}",
+            candidate.rank,
+            candidate.date.format("%Y-%m-%d"),
+            score,
+            origin
+        );
+        if let Some(inter) = forecast.intercept {
+            println!("        {:+.4} intercept", inter);
+        }
+        let mut contributions = candidate.contributions.clone();
+        contributions.sort_by(|a, b| b.weight.abs().partial_cmp(&a.weight.abs()).unwrap());
+        for c in contributions {
+            println!("        {:+.4} {}", c.weight, c.feature.label(locale));
+        }
+    }
+}
+
+fn print_evaluations(evaluations: &Vec<Evaluation>) {
+    println!(
+        "{:<20} {:<10} {:>6} {:>10} {:>8} {:>10}",
+        "recurrence", "method", "trials", "MAE(days)", "exact", "within"
+    );
+    for e in evaluations {
+        println!(
+            "{:<20} {:<10} {:>6} {:>10.2} {:>8.3} {:>10}",
+            e.recurrence,
+            e.method,
+            e.trials,
+            e.mae,
+            e.exact_rate,
+            format!("{:.3}(±{})", e.within_rate, e.tolerance)
+        );
+    }
+}
+
+fn print_inference(inference: &Inference) {
+    let dates = |dates: &Vec<Date<Utc>>| -> String {
+        dates
+            .iter()
+            .map(|d| d.format("%Y%m%d").to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    };
+    println!(
+        "DTSTART;VALUE=DATE:{}",
+        inference.rule.dtstart.format("%Y%m%d")
+    );
+    println!("RRULE:{}", inference.rule);
+    if !inference.exdates.is_empty() {
+        println!("EXDATE;VALUE=DATE:{}", dates(&inference.exdates));
+    }
+    if !inference.rdates.is_empty() {
+        println!("RDATE;VALUE=DATE:{}", dates(&inference.rdates));
+    }
+    println!(
+        "fit: {}/{} occurrences reproduced (recall {:.3}), {}/{} generated dates occurred (precision {:.3}), {} EXDATE, {} RDATE",
+        inference.matched,
+        inference.occurrences,
+        inference.recall(),
+        inference.matched,
+        inference.generated,
+        inference.precision(),
+        inference.exdates.len(),
+        inference.rdates.len()
+    );
+}
 
-#[test]
-fn example() -> Result<()> {
-    let weekday = Utc.ymd(2020, 4, 1).weekday();
-    print!("{}", weekday);
+fn print_reports(reports: &Vec<Report>) {
+    println!(
+        "{:<20} {:>6} {:<12} {:>10} {:>12}",
+        "recurrence", "events", "forecast", "score", "probability"
+    );
+    for r in reports {
+        if let Some(e) = &r.error {
+            println!("{:<20} {:>6} error: {}", r.recurrence, r.occurrences, e);
+            continue;
+        }
+        if r.forecasts.is_empty() {
+            println!("{:<20} {:>6} {:<12}", r.recurrence, r.occurrences, "-");
+        }
+        for f in &r.forecasts {
+            println!(
+                "{:<20} {:>6} {:<12} {:>10.4} {:>12}",
+                r.recurrence,
+                r.occurrences,
+                f.date,
+                f.score,
+                f.probability
+                    .map_or("-".to_string(), |p| format!("{:.4}", p))
+            );
+        }
+        if let Some(w) = &r.warning {
+            println!("{:<20} {:>6} warning: {}", r.recurrence, r.occurrences, w);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////
+// エラーを表示して終了する
+//-----------------------------------------
+// 終了コードは HeronError::exit_code
+//////////////////////////////////////////////////////////
+fn exit_with(error: HeronError) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(error.exit_code());
+}
+
+// オプションの値が不正
+fn invalid(option: &str, value: &str, hint: &str) -> HeronError {
+    HeronError::Input(format!("--{}: {} (Please {})", option, value, hint))
+}
+
+// 数値のオプション
+fn num_option<T: FromStr>(option: &str, value: &str) -> Result<T, HeronError> {
+    value
+        .parse::<T>()
+        .map_err(|_| invalid(option, value, "num"))
+}
+
+// 標準入力を EOF の行まで読む
+fn stdin_lines() -> Result<Vec<String>, HeronError> {
+    let mut lines = vec![];
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|e| HeronError::Input(format!("stdin: {}", e)))?;
+        if line == "EOF" {
+            break;
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+//////////////////////////////////////////////////////////
+// Option: --input
+//////////////////////////////////////////////////////////
+fn read_events(matches: &ArgMatches) -> Result<Vec<Date<Utc>>, HeronError> {
+    if let Some(_) = matches.value_of("input") {
+        let calendar_id = matches
+            .value_of("calendar_id")
+            .ok_or_else(|| HeronError::Input("Input calendar_id".to_string()))?;
+        let recurrence_name = matches
+            .value_of("recurrence_name")
+            .ok_or_else(|| HeronError::Input("Input reccurrence_name".to_string()))?;
+        return GoogleCalendar::new(calendar_id).events(recurrence_name);
+    }
+
+    history::parse_dates(&stdin_lines()?)
+}
+
+//////////////////////////////////////////////////////////
+// Option: --batch-file, --recurrence_name (batch)
+//-----------------------------------------
+// --input があれば -r のカンマ区切りの名前それぞれを
+// Google Calendar から取得する．なければ --batch-file
+// (省略時は標準入力から EOF まで) の "名前,日付" を読む
+//////////////////////////////////////////////////////////
+fn read_series(matches: &ArgMatches) -> Result<Vec<Series>, HeronError> {
+    let names: Option<Vec<&str>> = matches
+        .value_of("recurrence_name")
+        .map(|o| o.split(',').map(|n| n.trim()).collect());
+
+    if let Some(_) = matches.value_of("input") {
+        let calendar_id = matches
+            .value_of("calendar_id")
+            .ok_or_else(|| HeronError::Input("Input calendar_id".to_string()))?;
+        let names = names.ok_or_else(|| HeronError::Input("Input reccurrence_name".to_string()))?;
+        let source = GoogleCalendar::new(calendar_id);
+        return names
+            .iter()
+            .map(|name| {
+                Ok(Series {
+                    name: name.to_string(),
+                    events: source.events(name)?,
+                })
+            })
+            .collect();
+    }
+
+    match matches.value_of("batch-file") {
+        Some(path) => history::read_series_file(path),
+        None => batch::parse_series(&stdin_lines()?),
+    }
+}
+
+//////////////////////////////////////////////////////////
+// Option: --anchor, --anchor-file
+//-----------------------------------------
+// anchor の予定を --anchor-file，読み込んだ series，
+// Google Calendar (--input があるとき) の順に探す
+//////////////////////////////////////////////////////////
+fn resolve_anchors(
+    matches: &ArgMatches,
+    settings: &mut Settings,
+    series: &Vec<Series>,
+) -> Result<(), HeronError> {
+    for name in settings.anchors.values() {
+        if settings.anchor_events.contains_key(name) {
+            continue;
+        }
+        if let Ok(events) = series.events(name) {
+            settings.anchor_events.insert(name.clone(), events);
+            continue;
+        }
+        if let (Some(_), Some(calendar_id)) =
+            (matches.value_of("input"), matches.value_of("calendar_id"))
+        {
+            let events = GoogleCalendar::new(calendar_id).events(name)?;
+            settings.anchor_events.insert(name.clone(), events);
+        }
+    }
     Ok(())
 }
 
-#[test]
-fn print_typename<T>(_: T) {
-    println!("{}", std::any::type_name::<T>());
+//////////////////////////////////////////////////////////
+// Option: --output (省略時は標準出力)
+//////////////////////////////////////////////////////////
+fn write_output(matches: &ArgMatches, text: &str) -> Result<(), HeronError> {
+    match matches.value_of("output") {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| HeronError::Input(format!("{}: {}", path, e)))
+        }
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////
+// Option: --write-calendar
+//-----------------------------------------
+// 各予測の最有力の日を仮の予定として書き込む
+//////////////////////////////////////////////////////////
+fn write_calendar(
+    calendar_id: &str,
+    recurrence_name: &str,
+    forecasts: &Vec<Forecast>,
+) -> Result<(), HeronError> {
+    let dates = forecasts
+        .iter()
+        .filter_map(|f| f.best())
+        .map(|c| c.date.naive_utc())
+        .collect();
+    let r = google::google_calendar::write_forecasts(calendar_id, recurrence_name, &dates)?;
+    println!(
+        "{}: {} inserted, {} updated, {} deleted, {} kept",
+        recurrence_name, r.inserted, r.updated, r.deleted, r.kept
+    );
+    Ok(())
+}
+
+fn fiscal_year_option(matches: &ArgMatches) -> Result<FiscalYear, HeronError> {
+    let mut fiscal_year = FiscalYear::default();
+    if let Some(o) = matches.value_of("fiscal-year-start") {
+        fiscal_year =
+            FiscalYear::parse(o).ok_or_else(|| invalid("fiscal-year-start", o, "MM-DD"))?;
+    }
+    Ok(fiscal_year)
+}
+
+fn date_option(option: &str, value: &str) -> Result<Date<Utc>, HeronError> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map(|d| Date::from_utc(d, Utc))
+        .map_err(|_| invalid(option, value, "YYYY-MM-DD"))
+}
+
+fn holidays_option(matches: &ArgMatches) -> Result<Arc<dyn HolidayProvider>, HeronError> {
+    holiday::from_spec(matches.value_of("holidays").unwrap_or("jp")).map_err(HeronError::Input)
+}
+
+fn candidate_range_option(matches: &ArgMatches) -> Result<Vec<i64>, HeronError> {
+    let mut range_candidates: Vec<i64> = vec![];
+    if let Some(o) = matches.value_of("candidate-range") {
+        let num: i64 = num_option("candidate-range", o)?;
+        if num <= 0 || num % 2 == 0 {
+            return Err(invalid("candidate-range", o, "odd num > 0"));
+        }
+        let n = (num - 1) / 2;
+        range_candidates = (-n..=n).collect();
+    } else {
+        range_candidates = (-3..4).collect();
+    }
+    Ok(range_candidates)
 }
 
-fn fiscal_year_first_date(date: Date<Utc>) -> Date<Utc> {
-    let mut y = date.year();
-    let m = date.month();
-    if m == 1 || m == 2 || m == 3 {
-        y = y - 1;
+fn tuning_file_option(matches: &ArgMatches) -> String {
+    matches
+        .value_of("tuning-file")
+        .unwrap_or("tuning.json")
+        .to_string()
+}
+
+fn settings_option(matches: &ArgMatches) -> Result<Settings, HeronError> {
+    ///////////////////////////////////////////////////
+    // Option: --holidays
+    ///////////////////////////////////////////////////
+    let holidays = holidays_option(matches)?;
+
+    ///////////////////////////////////////////////////
+    // Option: --candidate-range
+    ///////////////////////////////////////////////////
+    let mut range_candidate = None;
+    if matches.is_present("candidate-range") {
+        range_candidate = Some(candidate_range_option(matches)?);
+    }
+
+    ///////////////////////////////////////////////////
+    // Option: --sampling-range
+    ///////////////////////////////////////////////////
+    let mut range_recurrence = None;
+    if let Some(o) = matches.value_of("sampling-range") {
+        let range = o
+            .split('-')
+            .map(|d| {
+                NaiveDate::parse_from_str(d.trim(), "%Y/%m/%d").map(|d| Date::from_utc(d, Utc))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|r| r.len() == 2 && r[0] <= r[1])
+            .ok_or_else(|| invalid("sampling-range", o, "YYYY/MM/DD-YYYY/MM/DD, FROM <= TO"))?;
+        range_recurrence = Some(range);
+    }
+
+    ////////////////////////////////////////////////////
+    // Option: --forecast_year, --count, --until, --between
+    // (どれもなければ次の 1 件)
+    ////////////////////////////////////////////////////
+    let mut horizon = Horizon::Next;
+    if let Some(o) = matches.value_of("forecast-year") {
+        horizon = Horizon::Year(
+            o.parse::<i32>()
+                .map_err(|_| invalid("forecast-year", o, "num"))?,
+        );
+    }
+    if let Some(o) = matches.value_of("count") {
+        let n = o
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| invalid("count", o, "num >= 1"))?;
+        horizon = Horizon::Count(n);
+    }
+    if let Some(o) = matches.value_of("until") {
+        horizon = Horizon::Until(date_option("until", o)?);
+    }
+    if let Some(o) = matches.values_of("between") {
+        let values: Vec<&str> = o.collect();
+        let dates = values
+            .iter()
+            .map(|d| date_option("between", d))
+            .collect::<Result<Vec<_>, _>>()?;
+        if dates[0] > dates[1] {
+            return Err(invalid("between", &values.join(" "), "FROM <= TO"));
+        }
+        horizon = Horizon::Between(dates[0], dates[1]);
+    }
+
+    ////////////////////////////////////////////////////
+    // Option: --anchor (NAME:ANCHOR のカンマ区切り．
+    // ANCHOR だけなら -r のリカーレンスに使う)
+    ////////////////////////////////////////////////////
+    let mut anchors = BTreeMap::new();
+    if let Some(o) = matches.value_of("anchor") {
+        let recurrence_name = matches.value_of("recurrence_name").unwrap_or("-");
+        for item in o.split(',') {
+            let (name, anchor) = match item.find(':') {
+                Some(i) => (&item[..i], &item[i + 1..]),
+                None => (recurrence_name, item),
+            };
+            anchors.insert(name.trim().to_string(), anchor.trim().to_string());
+        }
+    }
+    let mut anchor_events = BTreeMap::new();
+    if let Some(path) = matches.value_of("anchor-file") {
+        for s in history::read_series_file(path)? {
+            anchor_events.insert(s.name, s.events);
+        }
+    }
+
+    ////////////////////////////////////////////////////
+    // Option: --no-holidays, --weekdays, --blackout, --busy
+    ////////////////////////////////////////////////////
+    let mut common_constraints = Constraints::default();
+    common_constraints.no_holidays = matches.is_present("no-holidays");
+    if let Some(o) = matches.value_of("weekdays") {
+        common_constraints.weekdays = Some(
+            o.split(',')
+                .map(|w| DayOfWeek::parse(w).ok_or_else(|| invalid("weekdays", w, "mon, tue, ...")))
+                .collect::<Result<_, _>>()?,
+        );
+    }
+    if let Some(o) = matches.value_of("blackout") {
+        common_constraints.blackouts = o
+            .split(',')
+            .map(|b| {
+                Blackout::parse(b).ok_or_else(|| invalid("blackout", b, "YYYY-MM-DD:YYYY-MM-DD"))
+            })
+            .collect::<Result<_, _>>()?;
     }
-    return Utc.ymd(y, 4, 1);
+    if let Some(o) = matches.value_of("busy") {
+        common_constraints.busy = o
+            .split(',')
+            .map(|d| {
+                NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")
+                    .map_err(|_| invalid("busy", d, "YYYY-MM-DD"))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    ////////////////////////////////////////////////////
+    // Option: --constraints-file
+    ////////////////////////////////////////////////////
+    let path = matches
+        .value_of("constraints-file")
+        .unwrap_or("constraints.json");
+    let constraints =
+        constraint::load(path).map_err(|e| HeronError::Input(format!("{}: {}", path, e)))?;
+
+    ////////////////////////////////////////////////////
+    // Option: --tuning-file
+    ////////////////////////////////////////////////////
+    let path = tuning_file_option(matches);
+    let tunings = tuning::load(&path).map_err(|e| HeronError::Input(format!("{}: {}", path, e)))?;
+
+    let fiscal_year = fiscal_year_option(matches)?;
+    let mut options = method::Options::new(holidays);
+    options.fiscal_year = fiscal_year;
+
+    Ok(Settings {
+        method_name: matches.value_of("method").map(|m| m.to_string()),
+        range_candidate,
+        range_recurrence,
+        horizon,
+        tunings,
+        anchors,
+        anchor_events,
+        common_constraints,
+        constraints,
+        ..Settings::new(options)
+    })
+}
+
+fn new_forecaster(
+    method_name: &str,
+    options: &method::Options,
+) -> Result<Box<dyn Forecaster>, HeronError> {
+    method::new_forecaster(method_name, options).ok_or_else(|| {
+        HeronError::Input(format!(
+            "No matching method: {} (available: {})",
+            method_name,
+            method::names().join(", ")
+        ))
+    })
 }
 
 fn main() {
@@ -49,7 +582,7 @@ fn main() {
         )
         .arg(
             Arg::with_name("method") // オプションを定義
-                .help("Set the forecasting algorithm.") // ヘルプメッセージ
+                .help("Set the forecasting algorithm. [default: linear]") // ヘルプメッセージ
                 .short("m") // ショートコマンド
                 .long("method") // ロングコマンド
                 .takes_value(true), // 値を持つことを定義
@@ -75,6 +608,42 @@ fn main() {
                 .long("forecast-year") // ロングコマンド
                 .takes_value(true), // 値を持つことを定義
         )
+        .arg(
+            Arg::with_name("count") // オプションを定義
+                .help("Forecast the next N occurrences.") // ヘルプメッセージ
+                .short("n") // ショートコマンド
+                .long("count") // ロングコマンド
+                .takes_value(true) // 値を持つことを定義
+                .conflicts_with_all(&["forecast-year", "until", "between"]),
+        )
+        .arg(
+            Arg::with_name("until") // オプションを定義
+                .help("Forecast all occurrences until YYYY-MM-DD.") // ヘルプメッセージ
+                .long("until") // ロングコマンド
+                .takes_value(true) // 値を持つことを定義
+                .conflicts_with_all(&["forecast-year", "between"]),
+        )
+        .arg(
+            Arg::with_name("between") // オプションを定義
+                .help("Forecast all occurrences between two dates (YYYY-MM-DD YYYY-MM-DD).") // ヘルプメッセージ
+                .long("between") // ロングコマンド
+                .takes_value(true) // 値を持つことを定義
+                .number_of_values(2)
+                .value_names(&["FROM", "TO"])
+                .conflicts_with("forecast-year"),
+        )
+        .arg(
+            Arg::with_name("fiscal-year-start") // オプションを定義
+                .help("First day of the fiscal year in the form of MM-DD. [default: 04-01]") // ヘルプメッセージ
+                .long("fiscal-year-start") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("holidays") // オプションを定義
+                .help("Holiday providers joined by commas: jp, none, file:PATH (CSV or ICS). [default: jp]") // ヘルプメッセージ
+                .long("holidays") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
         .arg(
             Arg::with_name("calendar_id") // オプションを定義
                 .help("fib") // ヘルプメッセージ
@@ -82,6 +651,122 @@ fn main() {
                 .long("calendar_id") // ロングコマンド
                 .takes_value(true), // 値を持つことを定義
         )
+        .arg(
+            Arg::with_name("top") // オプションを定義
+                .help("Print the top N candidates with their scores.") // ヘルプメッセージ
+                .short("t") // ショートコマンド
+                .long("top") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("explain") // オプションを定義
+                .help("Explain the forecast: periods, pivot dates and feature contributions.") // ヘルプメッセージ
+                .short("e") // ショートコマンド
+                .long("explain"), // ロングコマンド
+        )
+        .arg(
+            Arg::with_name("lang") // オプションを定義
+                .help("Language of feature labels: ja, en. [default: ja]") // ヘルプメッセージ
+                .long("lang") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("holdout") // オプションを定義
+                .help("evaluate, tune: Number of latest occurrences to hide and forecast. [default: 3 (evaluate), 5 (tune)]") // ヘルプメッセージ
+                .long("holdout") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("tolerance") // オプションを定義
+                .help("evaluate, tune: Count a forecast within ±N days as a hit. [default: 3]") // ヘルプメッセージ
+                .long("tolerance") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("format") // オプションを定義
+                .help("Output format: table, json (evaluate, batch), ics (forecast, batch). [default: table]") // ヘルプメッセージ
+                .long("format") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("output") // オプションを定義
+                .help("Write the ics output to the FILE. [default: stdout]") // ヘルプメッセージ
+                .short("o") // ショートコマンド
+                .long("output") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("write-calendar") // オプションを定義
+                .help("Write forecasts to the CALENDAR_ID as tentative events.") // ヘルプメッセージ
+                .long("write-calendar") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("candidate-range") // オプションを定義
+                .help("Number of candidate days around the expected date (odd). [default: 7]") // ヘルプメッセージ
+                .long("candidate-range") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("tuning-file") // オプションを定義
+                .help("File to store tuned settings per recurrence. [default: tuning.json]") // ヘルプメッセージ
+                .long("tuning-file") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("anchor") // オプションを定義
+                .help("Forecast relative to another recurrence: ANCHOR, or NAME:ANCHOR joined by commas.") // ヘルプメッセージ
+                .long("anchor") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("anchor-file") // オプションを定義
+                .help("Read occurrences of anchors as lines of RECURRENCE_NAME,YYYY-MM-DD from the FILE.") // ヘルプメッセージ
+                .long("anchor-file") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("no-holidays") // オプションを定義
+                .help("Never forecast on holidays.") // ヘルプメッセージ
+                .long("no-holidays"), // ロングコマンド
+        )
+        .arg(
+            Arg::with_name("weekdays") // オプションを定義
+                .help("Allowed weekdays joined by commas, e.g. mon,tue,wed,thu,fri.") // ヘルプメッセージ
+                .long("weekdays") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("blackout") // オプションを定義
+                .help("Blackout periods YYYY-MM-DD:YYYY-MM-DD joined by commas.") // ヘルプメッセージ
+                .long("blackout") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("busy") // オプションを定義
+                .help("Busy dates YYYY-MM-DD joined by commas.") // ヘルプメッセージ
+                .long("busy") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("constraints-file") // オプションを定義
+                .help("File of constraints per recurrence. [default: constraints.json]") // ヘルプメッセージ
+                .long("constraints-file") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("batch-file") // オプションを定義
+                .help("batch, evaluate: Read lines of RECURRENCE_NAME,YYYY-MM-DD from the FILE. [default: stdin (batch)]") // ヘルプメッセージ
+                .long("batch-file") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
+        .arg(
+            Arg::with_name("jobs") // オプションを定義
+                .help("batch: Number of recurrences to forecast in parallel. [default: 4]") // ヘルプメッセージ
+                .short("j") // ショートコマンド
+                .long("jobs") // ロングコマンド
+                .takes_value(true), // 値を持つことを定義
+        )
         .arg(
             Arg::with_name("sampling-range") // オプションを定義
                 .help("Date range in the form of YYYY/MM/DD-YYYY/MM/DD.") // ヘルプメッセージ
@@ -94,123 +779,305 @@ fn main() {
     if let Some(c) = matches.value_of("command") {
         match c {
             "forecast" => {
-                let mut events: Vec<Date<Utc>> = vec![];
-                let mut _range_recurrence: Vec<Date<Utc>> = vec![];
-                let mut range_candidates: Vec<i64> = vec![];
-
-                //////////////////////////////////////////////////////////
-                // Option: --input
-                //////////////////////////////////////////////////////////
-                if let Some(_) = matches.value_of("input") {
-                    if let Some(calendar_id) = matches.value_of("calendar_id") {
-                        if let Some(recurrence_name) = matches.value_of("recurrence_name") {
-                            let events_list = google::google_calendar::get_oneday_schedule(
-                                calendar_id.to_string(),
-                                recurrence_name.to_string(),
-                            );
-                            events = events_list
-                                .items
-                                .iter()
-                                .map(|i| match &i.start {
-                                    Some(dt) => match &dt.date_time {
-                                        Some(d) => d.parse::<DateTime<Utc>>().unwrap().date(),
-                                        None => Date::from_utc(
-                                            NaiveDate::parse_from_str(
-                                                &dt.date.as_ref().unwrap(),
-                                                "%Y-%m-%d",
-                                            )
-                                            .unwrap(),
-                                            Utc,
-                                        ),
-                                    },
-                                    None => Utc.ymd(2000, 1, 1),
-                                })
-                                .collect();
-                        } else {
-                            println!("Input reccurrence_name");
-                        }
-                    } else {
-                        println!("Input calendar_id");
-                    }
-                } else {
-                    let stdin = io::stdin();
-                    let mut lines = stdin.lock().lines();
+                let events = read_events(&matches).unwrap_or_else(|e| exit_with(e));
+                let mut settings = settings_option(&matches).unwrap_or_else(|e| exit_with(e));
+                resolve_anchors(&matches, &mut settings, &vec![]).unwrap_or_else(|e| exit_with(e));
 
-                    while let Some(line) = lines.next() {
-                        // let length: i32 = line.unwrap().trim().parse().unwrap();
-                        if line.as_ref().unwrap() == &"EOF".to_string() {
-                            break;
-                        }
-                        events.push(Date::from_utc(
-                            NaiveDate::parse_from_str(&line.unwrap(), "%Y-%m-%d").unwrap(),
-                            Utc,
-                        ));
-                    }
+                ///////////////////////////////////////////////////
+                // Option: --top
+                ///////////////////////////////////////////////////
+                let mut top: usize = 0;
+                if let Some(o) = matches.value_of("top") {
+                    top = num_option("top", o).unwrap_or_else(|e| exit_with(e));
+                }
+
+                ///////////////////////////////////////////////////
+                // Option: --explain (--top がなければ上位 3 件)
+                ///////////////////////////////////////////////////
+                let explain = matches.is_present("explain");
+                let mut explain_top = 3;
+                if matches.is_present("top") {
+                    explain_top = top;
+                }
+
+                ///////////////////////////////////////////////////
+                // Option: --lang
+                ///////////////////////////////////////////////////
+                let mut locale = Locale::Ja;
+                if let Some(o) = matches.value_of("lang") {
+                    locale = Locale::parse(o)
+                        .unwrap_or_else(|| exit_with(invalid("lang", o, "ja or en")));
                 }
 
+                let recurrence_name = matches.value_of("recurrence_name");
+                let forecasts = forecast_series(recurrence_name, &events, &settings)
+                    .unwrap_or_else(|e| exit_with(e));
+
                 ///////////////////////////////////////////////////
-                // Option: --sampling-range
+                // Option: --format ics
                 ///////////////////////////////////////////////////
-                if let Some(o) = matches.value_of("sampling-range") {
-                    _range_recurrence = o
-                        .split("-")
-                        .map(|d| {
-                            Date::from_utc(NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap(), Utc)
-                        })
+                if matches.value_of("format") == Some("ics") {
+                    let name = recurrence_name.unwrap_or("forecast").to_string();
+                    let named = forecasts
+                        .iter()
+                        .map(|f| (name.clone(), f.clone()))
                         .collect();
+                    write_output(&matches, &ical::to_ics(&named)).unwrap_or_else(|e| exit_with(e));
                 } else {
-                    let first = fiscal_year_first_date(events[0]);
-                    let last = events.last().unwrap().clone();
-                    _range_recurrence = vec![first, last];
+                    if forecasts.is_empty() {
+                        println!("forecast: none");
+                    }
+                    for ranked in forecasts.iter() {
+                        print_forecast(ranked, top, locale);
+                        if explain {
+                            print_explanation(ranked, explain_top, locale);
+                        }
+                    }
+                }
+
+                ///////////////////////////////////////////////////
+                // Option: --write-calendar
+                ///////////////////////////////////////////////////
+                if let Some(calendar_id) = matches.value_of("write-calendar") {
+                    let r = recurrence_name.unwrap_or_else(|| {
+                        exit_with(HeronError::Input("Input reccurrence_name".to_string()))
+                    });
+                    write_calendar(calendar_id, r, &forecasts).unwrap_or_else(|e| exit_with(e));
                 }
+            }
+            "batch" => {
+                let mut settings = settings_option(&matches).unwrap_or_else(|e| exit_with(e));
 
                 ///////////////////////////////////////////////////
-                // Option: --method
+                // Option: --jobs
                 ///////////////////////////////////////////////////
+                let mut jobs = batch::DEFAULT_JOBS;
+                if let Some(o) = matches.value_of("jobs") {
+                    jobs = num_option("jobs", o).unwrap_or_else(|e| exit_with(e));
+                }
 
-                // fib
+                let mut series = read_series(&matches).unwrap_or_else(|e| exit_with(e));
+                resolve_anchors(&matches, &mut settings, &series).unwrap_or_else(|e| exit_with(e));
+
+                // -r があればその名前だけを予測する
+                if let Some(o) = matches.value_of("recurrence_name") {
+                    let names: Vec<&str> = o.split(',').map(|n| n.trim()).collect();
+                    series.retain(|s| names.contains(&s.name.as_str()));
+                }
+                let results = batch::run(series, jobs, move |s| {
+                    forecast_series(Some(s.name.as_str()), &s.events, &settings)
+                });
+                let reports: Vec<Report> = results.iter().map(|(s, r)| Report::new(s, r)).collect();
 
                 ///////////////////////////////////////////////////
-                // Option: --candidate-range
+                // Option: --format
                 ///////////////////////////////////////////////////
-                if let Some(o) = matches.value_of("candidate-range") {
-                    let num: i64 = o.parse::<i64>().expect("Please num");
-                    if (num > 0) && (num % 2 == 1) {
-                        let n = (num - 1) / 2;
-                        range_candidates = (-n..=n).collect();
-                    }
-                } else {
-                    range_candidates = (-3..4).collect();
-                }
-
-                ////////////////////////////////////////////////////
-                // Option: --forecast_year
-                ////////////////////////////////////////////////////
-                if let Some(o) = matches.value_of("forecast-year") {
-                    let forecast_year: i32 = o.parse::<i32>().expect("Please num");
-                    let forecast_start = Utc.ymd(forecast_year, 4, 1);
-                    let forecast_end = Utc.ymd(forecast_year + 1, 4, 1);
-                    loop {
-                        let forecasted =
-                            forecaster::forecast(&_range_recurrence, &range_candidates, &events);
-                        events.push(forecasted);
-                        if forecasted < forecast_start {
-                            continue;
+                match matches.value_of("format").unwrap_or("table") {
+                    "json" => println!("{}", serde_json::to_string_pretty(&reports).unwrap()),
+                    "ics" => {
+                        let mut forecasts = vec![];
+                        for (s, r) in results.iter() {
+                            match r {
+                                Ok(f) => {
+                                    forecasts.extend(f.iter().map(|f| (s.name.clone(), f.clone())))
+                                }
+                                Err(e) => eprintln!("{}: {}", s.name, e),
+                            }
                         }
-                        if forecasted > forecast_end {
-                            break;
+                        write_output(&matches, &ical::to_ics(&forecasts))
+                            .unwrap_or_else(|e| exit_with(e));
+                    }
+                    _ => print_reports(&reports),
+                }
+
+                ///////////////////////////////////////////////////
+                // Option: --write-calendar
+                ///////////////////////////////////////////////////
+                // 書き込めなかったリカーレンスがあれば最初のエラーで終了する
+                if let Some(calendar_id) = matches.value_of("write-calendar") {
+                    let mut failed = None;
+                    for (s, r) in results.iter() {
+                        if let Ok(forecasts) = r {
+                            if let Err(e) = write_calendar(calendar_id, &s.name, forecasts) {
+                                eprintln!("{}: {}", s.name, e);
+                                failed = failed.or(Some(e));
+                            }
                         }
-                        println!("forecast: {:?}", forecasted);
-                        _range_recurrence[1] = forecasted;
                     }
+                    if let Some(e) = failed {
+                        exit_with(e);
+                    }
+                }
+            }
+            "evaluate" => {
+                ///////////////////////////////////////////////////
+                // Option: --batch-file, --input (batch と同じく複数のリカーレンス)
+                ///////////////////////////////////////////////////
+                let series = if matches.is_present("batch-file") || matches.is_present("input") {
+                    let mut series = read_series(&matches).unwrap_or_else(|e| exit_with(e));
+                    if let Some(o) = matches.value_of("recurrence_name") {
+                        let names: Vec<&str> = o.split(',').map(|n| n.trim()).collect();
+                        series.retain(|s| names.contains(&s.name.as_str()));
+                    }
+                    series
                 } else {
-                    let forecasted =
-                        forecaster::forecast(&_range_recurrence, &range_candidates, &events);
-                    println!("forecast: {:?}", forecasted);
+                    vec![Series {
+                        name: matches
+                            .value_of("recurrence_name")
+                            .unwrap_or("-")
+                            .to_string(),
+                        events: read_events(&matches).unwrap_or_else(|e| exit_with(e)),
+                    }]
+                };
+
+                let holidays = holidays_option(&matches).unwrap_or_else(|e| exit_with(e));
+                let fiscal_year = fiscal_year_option(&matches).unwrap_or_else(|e| exit_with(e));
+
+                ///////////////////////////////////////////////////
+                // Option: --holdout, --tolerance
+                ///////////////////////////////////////////////////
+                let mut holdout = backtest::DEFAULT_HOLDOUT;
+                if let Some(o) = matches.value_of("holdout") {
+                    holdout = num_option("holdout", o).unwrap_or_else(|e| exit_with(e));
+                }
+                let mut tolerance = backtest::DEFAULT_TOLERANCE;
+                if let Some(o) = matches.value_of("tolerance") {
+                    tolerance = num_option("tolerance", o).unwrap_or_else(|e| exit_with(e));
+                }
+
+                let backtest = Backtest {
+                    holdout,
+                    tolerance,
+                    range_candidate: candidate_range_option(&matches)
+                        .unwrap_or_else(|e| exit_with(e)),
+                    fiscal_year,
+                    sampling_years: None,
+                };
+                let mut options = method::Options::new(holidays);
+                options.fiscal_year = fiscal_year;
+
+                ///////////////////////////////////////////////////
+                // Option: --method (カンマ区切り，デフォルトで全手法)
+                ///////////////////////////////////////////////////
+                let method_names = match matches.value_of("method") {
+                    Some(o) => o.split(',').collect(),
+                    None => method::names(),
+                };
+
+                for method_name in method_names.iter() {
+                    new_forecaster(method_name, &options).unwrap_or_else(|e| exit_with(e));
+                }
+
+                // 評価できなかった組は，リカーレンスが複数あれば飛ばして続ける
+                let mut evaluations: Vec<Evaluation> = vec![];
+                for s in series.iter() {
+                    for method_name in method_names.iter() {
+                        let mut forecaster =
+                            new_forecaster(method_name, &options).unwrap_or_else(|e| exit_with(e));
+                        match backtest.run(forecaster.as_mut(), &s.events) {
+                            Ok(trials) => evaluations.push(Evaluation::new(
+                                &s.name,
+                                method_name,
+                                &trials,
+                                tolerance,
+                            )),
+                            Err(e) if series.len() == 1 => exit_with(e),
+                            Err(e) => eprintln!("{} ({}): {}", s.name, method_name, e),
+                        }
+                    }
+                }
+
+                ///////////////////////////////////////////////////
+                // Option: --format
+                ///////////////////////////////////////////////////
+                match matches.value_of("format").unwrap_or("table") {
+                    "json" => println!("{}", serde_json::to_string_pretty(&evaluations).unwrap()),
+                    _ => print_evaluations(&evaluations),
+                }
+            }
+            "tune" => {
+                let events = read_events(&matches).unwrap_or_else(|e| exit_with(e));
+                let recurrence_name = matches.value_of("recurrence_name").unwrap_or_else(|| {
+                    exit_with(HeronError::Input("Input reccurrence_name".to_string()))
+                });
+
+                let holidays = holidays_option(&matches).unwrap_or_else(|e| exit_with(e));
+                let fiscal_year = fiscal_year_option(&matches).unwrap_or_else(|e| exit_with(e));
+                let mut options = method::Options::new(holidays);
+                options.fiscal_year = fiscal_year;
+                let method_name = matches.value_of("method").unwrap_or(method::DEFAULT_METHOD);
+                new_forecaster(method_name, &options).unwrap_or_else(|e| exit_with(e));
+
+                let mut holdout = tuning::DEFAULT_HOLDOUT;
+                if let Some(o) = matches.value_of("holdout") {
+                    holdout = num_option("holdout", o).unwrap_or_else(|e| exit_with(e));
+                }
+                let mut tolerance = backtest::DEFAULT_TOLERANCE;
+                if let Some(o) = matches.value_of("tolerance") {
+                    tolerance = num_option("tolerance", o).unwrap_or_else(|e| exit_with(e));
+                }
+
+                let tuned = match tuning::tune(
+                    method_name,
+                    &options,
+                    &events,
+                    fiscal_year,
+                    holdout,
+                    tolerance,
+                ) {
+                    Some(t) => t,
+                    None => exit_with(HeronError::InsufficientHistory {
+                        found: events.len(),
+                        required: backtest::MIN_HISTORY + 1,
+                    }),
+                };
+                println!(
+                    "{}: method={} candidate-range={} sampling-years={} max-period={} MAE={:.2} within(±{})={:.3}",
+                    recurrence_name,
+                    tuned.method,
+                    tuned.candidate_width,
+                    tuned.sampling_years.map_or("all".to_string(), |y| y.to_string()),
+                    tuned.max_period.map_or("none".to_string(), |p| p.to_string()),
+                    tuned.mae,
+                    tolerance,
+                    tuned.within_rate
+                );
+
+                let path = tuning_file_option(&matches);
+                let mut tunings = tuning::load(&path)
+                    .unwrap_or_else(|e| exit_with(HeronError::Input(format!("{}: {}", path, e))));
+                tunings.insert(recurrence_name.to_string(), tuned);
+                tuning::save(&path, &tunings)
+                    .unwrap_or_else(|e| exit_with(HeronError::Input(format!("{}: {}", path, e))));
+                println!("Saved to {}", path);
+            }
+            "rrule" => {
+                let events = read_events(&matches).unwrap_or_else(|e| exit_with(e));
+                let inferences = rrule::infer(&events);
+                if inferences.is_empty() {
+                    exit_with(HeronError::InsufficientHistory {
+                        found: events.len(),
+                        required: 1,
+                    });
+                }
+
+                ///////////////////////////////////////////////////
+                // Option: --top (次点の規則も表示する)
+                ///////////////////////////////////////////////////
+                let mut top: usize = 1;
+                if let Some(o) = matches.value_of("top") {
+                    top = num_option("top", o).unwrap_or_else(|e| exit_with(e));
+                }
+
+                for (i, inference) in inferences.iter().take(top.max(1)).enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    print_inference(inference);
                 }
             }
             "show" => println!("fib"),
-            _ => println!("No matching command"),
+            _ => exit_with(HeronError::Input("No matching command".to_string())),
         }
     }
 }
@@ -0,0 +1,281 @@
+use chrono::{Date, Utc};
+use std::collections::BTreeMap;
+
+use crate::error::HeronError;
+use crate::forecast::anchor::AnchorForecaster;
+use crate::forecast::backtest;
+use crate::forecast::coldstart::ColdStartForecaster;
+use crate::forecast::constraint::{self, ConstrainedForecaster, Constraints};
+use crate::forecast::horizon::Horizon;
+use crate::forecast::method::{self, Forecaster};
+use crate::forecast::result::Forecast;
+use crate::forecast::tuning::Tuning;
+
+//////////////////////////////////////////////////////////
+// 1 つのリカーレンスを予測するための設定
+//-----------------------------------------
+// None のものは tune で保存した設定，なければデフォルトを使う
+//////////////////////////////////////////////////////////
+#[derive(Clone)]
+pub struct Settings {
+    pub options: method::Options,
+    pub method_name: Option<String>,
+    pub range_candidate: Option<Vec<i64>>,
+    pub range_recurrence: Option<Vec<Date<Utc>>>,
+    pub horizon: Horizon,
+    pub tunings: BTreeMap<String, Tuning>,
+    // 予測するリカーレンス名 -> anchor のリカーレンス名
+    pub anchors: BTreeMap<String, String>,
+    pub anchor_events: BTreeMap<String, Vec<Date<Utc>>>,
+    // 全リカーレンスに共通の制約とリカーレンスごとの制約
+    pub common_constraints: Constraints,
+    pub constraints: BTreeMap<String, Constraints>,
+}
+
+impl Settings {
+    // tune の結果も anchor も制約もない設定
+    pub fn new(options: method::Options) -> Settings {
+        Settings {
+            options,
+            method_name: None,
+            range_candidate: None,
+            range_recurrence: None,
+            horizon: Horizon::Next,
+            tunings: BTreeMap::new(),
+            anchors: BTreeMap::new(),
+            anchor_events: BTreeMap::new(),
+            common_constraints: Constraints::default(),
+            constraints: BTreeMap::new(),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////
+// 1 つのリカーレンスを予測する
+//-----------------------------------------
+// settings.horizon の範囲の予定を日付順に返す
+// (Next なら次の 1 件)
+//////////////////////////////////////////////////////////
+pub fn forecast_series(
+    recurrence_name: Option<&str>,
+    events: &Vec<Date<Utc>>,
+    settings: &Settings,
+) -> Result<Vec<Forecast>, HeronError> {
+    if events.is_empty() {
+        return Err(HeronError::InsufficientHistory {
+            found: 0,
+            required: 1,
+        });
+    }
+
+    // tune で保存した設定は，手法が一致するときだけ使う
+    let mut tuned = recurrence_name.and_then(|r| settings.tunings.get(r).cloned());
+    let method_name = match (&settings.method_name, &tuned) {
+        (Some(m), _) => m.clone(),
+        (None, Some(t)) => t.method.clone(),
+        (None, None) => method::DEFAULT_METHOD.to_string(),
+    };
+    if let Some(t) = &tuned {
+        if t.method != method_name {
+            tuned = None;
+        }
+    }
+
+    let mut options = settings.options.clone();
+    if let Some(t) = &tuned {
+        options.max_period = t.max_period;
+    }
+    let mut forecaster = match method::new_forecaster(&method_name, &options) {
+        Some(f) => f,
+        None => {
+            return Err(HeronError::Input(format!(
+                "No matching method: {} (available: {})",
+                method_name,
+                method::names().join(", ")
+            )))
+        }
+    };
+
+    // 履歴が短ければ周期や回帰を使わずに予測する
+    if events.len() < backtest::MIN_HISTORY {
+        forecaster = Box::new(ColdStartForecaster::new(&options));
+    }
+
+    let range_recurrence = match &settings.range_recurrence {
+        Some(r) => r.clone(),
+        None => {
+            let sampling_years = tuned.as_ref().and_then(|t| t.sampling_years);
+            settings
+                .options
+                .fiscal_year
+                .sampling_range(events, sampling_years)
+        }
+    };
+    let range_candidate = match (&settings.range_candidate, &tuned) {
+        (Some(r), _) => r.clone(),
+        (None, Some(t)) => t.range_candidate(),
+        (None, None) => (-3..4).collect(),
+    };
+
+    // anchor があれば，anchor の実績と予測に対する差でも予測する
+    if let Some(anchor_name) = settings.anchors.get(recurrence_name.unwrap_or("-")) {
+        let anchor_events = match settings.anchor_events.get(anchor_name) {
+            Some(a) => a,
+            None => {
+                return Err(HeronError::Input(format!(
+                    "No occurrences of the anchor: {}",
+                    anchor_name
+                )))
+            }
+        };
+        let mut anchor_settings = settings.clone();
+        anchor_settings.anchors.clear();
+        // 範囲の始まりより前の anchor の予測も使う
+        if let Some((_, until)) = settings.horizon.window(settings.options.fiscal_year) {
+            anchor_settings.horizon = Horizon::Until(until);
+        }
+        let mut anchors = anchor_events.clone();
+        if let Ok(forecasts) = forecast_series(Some(anchor_name), anchor_events, &anchor_settings) {
+            anchors.extend(forecasts.iter().filter_map(|f| f.best()).map(|c| c.date));
+        }
+        forecaster = Box::new(AnchorForecaster::new(forecaster, anchors, &options));
+    }
+
+    // 制約に反する候補日は順位づけの前に除く
+    let constraints = match recurrence_name.and_then(|r| settings.constraints.get(r)) {
+        Some(c) => settings.common_constraints.merge(c),
+        None => settings.common_constraints.clone(),
+    };
+    if !constraints.is_empty() {
+        forecaster = Box::new(ConstrainedForecaster::new(
+            forecaster,
+            constraints,
+            &options,
+        ));
+    }
+
+    forecaster.fit(events, &range_recurrence, &range_candidate)?;
+    let (from, until) = match settings.horizon.window(settings.options.fiscal_year) {
+        Some(w) => w,
+        None => return forecast_steps(forecaster.as_ref(), events, settings.horizon),
+    };
+    let forecasts = forecaster.forecast_until(events, until);
+    check_blocked(forecaster.as_ref(), events, &forecasts, Some(until))?;
+    Ok(match from {
+        Some(from) => forecasts
+            .into_iter()
+            .filter(|f| f.best().map_or(false, |c| c.date >= from))
+            .collect(),
+        None => forecasts,
+    })
+}
+
+// 日付で区切らない範囲 (Next, Count)
+fn forecast_steps(
+    forecaster: &dyn Forecaster,
+    events: &Vec<Date<Utc>>,
+    horizon: Horizon,
+) -> Result<Vec<Forecast>, HeronError> {
+    match horizon {
+        Horizon::Count(n) => {
+            let forecasts = forecaster.forecast_next(events, n);
+            if forecasts.len() < n.min(method::MAX_STEPS) {
+                check_blocked(forecaster, events, &forecasts, None)?;
+            }
+            Ok(forecasts)
+        }
+        _ => {
+            let ranked = forecaster.rank(events);
+            if ranked.candidates.is_empty() {
+                return Err(HeronError::NoCandidates(constraint::report(
+                    &ranked.rejected,
+                )));
+            }
+            Ok(vec![ranked])
+        }
+    }
+}
+
+// 範囲の途中で全ての候補日が制約に反して止まっていないか
+fn check_blocked(
+    forecaster: &dyn Forecaster,
+    events: &Vec<Date<Utc>>,
+    forecasts: &Vec<Forecast>,
+    until: Option<Date<Utc>>,
+) -> Result<(), HeronError> {
+    let mut history = events.clone();
+    history.extend(forecasts.iter().filter_map(|f| f.best()).map(|c| c.date));
+    let latest = *history.last().unwrap();
+    let next = forecaster.rank(&history);
+    if next.after(latest).is_some() {
+        return Ok(());
+    }
+    let rejected: Vec<_> = next
+        .rejected
+        .into_iter()
+        .filter(|r| r.date > latest && until.map_or(true, |u| r.date <= u))
+        .collect();
+    if rejected.is_empty() {
+        return Ok(());
+    }
+    Err(HeronError::NoCandidates(constraint::report(&rejected)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::holiday::NoHolidays;
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    // 2017-04-12 から 2021-04-14 まで毎月第 2 水曜日
+    fn seminar() -> Vec<Date<Utc>> {
+        (0..49)
+            .map(|i| {
+                let first = Utc.ymd(2017 + (3 + i) / 12, ((3 + i) % 12 + 1) as u32, 1);
+                let offset = (7 + 2 - first.weekday().num_days_from_monday() as i32) % 7;
+                first + Duration::days(offset as i64 + 7)
+            })
+            .collect()
+    }
+
+    fn dates(horizon: Horizon) -> Vec<Date<Utc>> {
+        let mut settings = Settings::new(method::Options::new(Arc::new(NoHolidays)));
+        settings.horizon = horizon;
+        forecast_series(Some("seminar"), &seminar(), &settings)
+            .unwrap()
+            .iter()
+            .filter_map(|f| f.best())
+            .map(|c| c.date)
+            .collect()
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(
+            dates(Horizon::Count(3)),
+            vec![
+                Utc.ymd(2021, 5, 12),
+                Utc.ymd(2021, 6, 9),
+                Utc.ymd(2021, 7, 14)
+            ]
+        );
+    }
+
+    #[test]
+    fn until() {
+        assert_eq!(
+            dates(Horizon::Until(Utc.ymd(2021, 6, 30))),
+            vec![Utc.ymd(2021, 5, 12), Utc.ymd(2021, 6, 9)]
+        );
+    }
+
+    #[test]
+    fn between() {
+        assert_eq!(
+            dates(Horizon::Between(Utc.ymd(2021, 6, 1), Utc.ymd(2021, 7, 31))),
+            vec![Utc.ymd(2021, 6, 9), Utc.ymd(2021, 7, 14)]
+        );
+    }
+}